@@ -72,6 +72,49 @@ fn get_100() {
     }
 }
 
+// Fits inline in the set's slots.
+#[allow(dead_code)]
+struct Small<const N: usize>(u32);
+
+// Too large to be inlined, boxed.
+#[allow(dead_code)]
+struct Large<const N: usize>([u64; 4]);
+
+macro_rules! insert_10 {
+    ($set:ident, $ty:ident, $value:expr) => {
+        $set.insert($ty::<0>($value));
+        $set.insert($ty::<1>($value));
+        $set.insert($ty::<2>($value));
+        $set.insert($ty::<3>($value));
+        $set.insert($ty::<4>($value));
+        $set.insert($ty::<5>($value));
+        $set.insert($ty::<6>($value));
+        $set.insert($ty::<7>($value));
+        $set.insert($ty::<8>($value));
+        $set.insert($ty::<9>($value));
+    };
+}
+
+#[no_mangle]
+#[inline(never)]
+fn insert_small_10() {
+    let mut set = ErasedSet::new();
+
+    insert_10!(set, Small, 0);
+
+    black_box(set);
+}
+
+#[no_mangle]
+#[inline(never)]
+fn insert_large_10() {
+    let mut set = ErasedSet::new();
+
+    insert_10!(set, Large, [0; 4]);
+
+    black_box(set);
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let benches = [
         Scenario::new(insert_1),
@@ -80,6 +123,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Scenario::new(get_1),
         Scenario::new(get_10),
         Scenario::new(get_100),
+        Scenario::new(insert_small_10),
+        Scenario::new(insert_large_10),
     ];
 
     let runner =
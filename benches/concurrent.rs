@@ -0,0 +1,94 @@
+use std::sync::RwLock;
+use std::thread;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use erased_set::{ErasedConcurrentSet, ErasedSyncSet};
+
+const ITERATIONS: usize = 1_000;
+
+struct T<const N: usize>(usize);
+
+/// Runs `$f::<N>($set)` on 4 threads, one per type `T<N>`.
+macro_rules! on_4_threads {
+    ($f:ident, $set:expr) => {
+        thread::scope(|s| {
+            s.spawn(|| $f::<0>($set));
+            s.spawn(|| $f::<1>($set));
+            s.spawn(|| $f::<2>($set));
+            s.spawn(|| $f::<3>($set));
+        })
+    };
+}
+
+fn locked_set() -> RwLock<ErasedSyncSet> {
+    let mut set = ErasedSyncSet::new();
+    set.insert(T::<0>(0));
+    set.insert(T::<1>(0));
+    set.insert(T::<2>(0));
+    set.insert(T::<3>(0));
+    RwLock::new(set)
+}
+
+fn concurrent_set() -> ErasedConcurrentSet {
+    let set = ErasedConcurrentSet::new();
+    set.insert(T::<0>(0));
+    set.insert(T::<1>(0));
+    set.insert(T::<2>(0));
+    set.insert(T::<3>(0));
+    set
+}
+
+fn write_locked<const N: usize>(set: &RwLock<ErasedSyncSet>) {
+    for _ in 0..ITERATIONS {
+        set.write().unwrap().get_mut::<T<N>>().unwrap().0 += 1;
+    }
+}
+
+fn write_concurrent<const N: usize>(set: &ErasedConcurrentSet) {
+    for _ in 0..ITERATIONS {
+        set.write::<T<N>>().unwrap().0 += 1;
+    }
+}
+
+fn read_locked<const N: usize>(set: &RwLock<ErasedSyncSet>) {
+    for _ in 0..ITERATIONS {
+        black_box(set.read().unwrap().get::<T<N>>().unwrap().0);
+    }
+}
+
+fn read_concurrent<const N: usize>(set: &ErasedConcurrentSet) {
+    for _ in 0..ITERATIONS {
+        black_box(set.read::<T<N>>().unwrap().0);
+    }
+}
+
+pub fn write_bench(c: &mut Criterion) {
+    let set = locked_set();
+    c.bench_function(
+        "write 4 types from 4 threads (RwLock<ErasedSyncSet>)",
+        |b| {
+            b.iter(|| on_4_threads!(write_locked, &set));
+        },
+    );
+
+    let set = concurrent_set();
+    c.bench_function("write 4 types from 4 threads (ErasedConcurrentSet)", |b| {
+        b.iter(|| on_4_threads!(write_concurrent, &set));
+    });
+}
+
+pub fn read_bench(c: &mut Criterion) {
+    let set = locked_set();
+    c.bench_function("read 4 types from 4 threads (RwLock<ErasedSyncSet>)", |b| {
+        b.iter(|| on_4_threads!(read_locked, &set));
+    });
+
+    let set = concurrent_set();
+    c.bench_function("read 4 types from 4 threads (ErasedConcurrentSet)", |b| {
+        b.iter(|| on_4_threads!(read_concurrent, &set));
+    });
+}
+
+criterion_group!(write, write_bench);
+criterion_group!(read, read_bench);
+criterion_main!(write, read);
@@ -72,6 +72,72 @@ pub fn get_bench(c: &mut Criterion) {
     c.bench_function("get 100", |b| b.iter(get_100));
 }
 
+erased_set::impl_erased_set! {
+    #[derive(Default)]
+    struct VecErasedSet: Any in erased_set::storage::VecStorage;
+}
+
+#[cfg(feature = "hashbrown")]
+erased_set::impl_erased_set! {
+    #[derive(Default)]
+    struct HashErasedSet: Any in erased_set::storage::HashStorage;
+}
+
+struct T<const A: usize, const B: usize>;
+
+/// Expands `$m!($set, T<A, B>)` for 80 distinct types.
+macro_rules! for_each_type {
+    ($m:ident, $set:ident) => {
+        for_each_type!(@outer $m, $set, [0 1 2 3 4 5 6 7]);
+    };
+    (@outer $m:ident, $set:ident, [$($a:literal)*]) => {
+        $(for_each_type!(@inner $m, $set, $a, [0 1 2 3 4 5 6 7 8 9]);)*
+    };
+    (@inner $m:ident, $set:ident, $a:literal, [$($b:literal)*]) => {
+        $($m!($set, T<$a, $b>);)*
+    };
+}
+
+macro_rules! insert {
+    ($set:ident, $ty:ty) => {
+        $set.insert::<$ty>(T);
+    };
+}
+
+macro_rules! get {
+    ($set:ident, $ty:ty) => {
+        black_box($set.get::<$ty>());
+    };
+}
+
+macro_rules! bench_backend {
+    ($c:ident, $name:literal, $set:ty) => {
+        $c.bench_function(concat!("insert 80 types (", $name, ")"), |b| {
+            b.iter(|| {
+                let mut set = <$set>::new();
+                for_each_type!(insert, set);
+                black_box(set);
+            });
+        });
+
+        $c.bench_function(concat!("get 80 types (", $name, ")"), |b| {
+            let mut set = <$set>::new();
+            for_each_type!(insert, set);
+            b.iter(|| {
+                for_each_type!(get, set);
+            });
+        });
+    };
+}
+
+pub fn backend_bench(c: &mut Criterion) {
+    bench_backend!(c, "btree", ErasedSet);
+    bench_backend!(c, "vec", VecErasedSet);
+    #[cfg(feature = "hashbrown")]
+    bench_backend!(c, "hash", HashErasedSet);
+}
+
 criterion_group!(insert, insert_bench);
 criterion_group!(get, get_bench);
-criterion_main!(insert, get);
+criterion_group!(backend, backend_bench);
+criterion_main!(insert, get, backend);
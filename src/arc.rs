@@ -0,0 +1,377 @@
+use ::alloc::collections::BTreeMap;
+use ::alloc::sync::{Arc, Weak};
+use ::core::any::{Any, TypeId};
+
+macro_rules! impl_erased_arc_set {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $(+ $bound:ident)*;
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            inner: BTreeMap<TypeId, Arc<dyn Any $(+ $bound)*>>,
+            #[cfg(debug_assertions)]
+            debug_type_names: BTreeMap<TypeId, &'static str>,
+        }
+
+        impl $name {
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`].")]
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// ```
+            #[must_use]
+            pub fn new() -> Self {
+                Self {
+                    inner: BTreeMap::new(),
+                    #[cfg(debug_assertions)]
+                    debug_type_names: BTreeMap::new(),
+                }
+            }
+
+            /// Returns `true` if the set contains no instances of any type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert!(set.is_empty());
+            /// ```
+            #[must_use]
+            pub fn is_empty(&self) -> bool {
+                self.inner.is_empty()
+            }
+
+            /// Returns the number of types in the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.insert("a");
+            /// assert_eq!(set.len(), 2);
+            /// ```
+            #[must_use]
+            pub fn len(&self) -> usize {
+                self.inner.len()
+            }
+
+            /// Clears the set, dropping its handles to the values.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.clear();
+            /// assert!(set.is_empty());
+            /// ```
+            pub fn clear(&mut self) {
+                self.inner.clear();
+
+                #[cfg(debug_assertions)]
+                self.debug_type_names.clear();
+            }
+
+            /// Returns `true` if the set contains an instance of `T`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u8>());
+            /// ```
+            #[must_use]
+            pub fn contains<T>(&self) -> bool
+            where
+                T: Any,
+            {
+                self.inner.contains_key(&TypeId::of::<T>())
+            }
+
+            /// Returns a reference to an instance of `T`.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// assert_eq!(set.get::<u8>(), None);
+            /// ```
+            #[must_use]
+            pub fn get<T>(&self) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.inner.get(&TypeId::of::<T>()).map(|arc| {
+                    // Sanity check
+                    debug_assert_eq!(Any::type_id(&**arc), TypeId::of::<T>());
+
+                    let ptr = (Arc::as_ptr(arc) as *const dyn Any).cast::<T>();
+
+                    unsafe { &*ptr }
+                })
+            }
+
+            /// Returns a mutable reference to an instance of `T`, if the set has the only
+            /// handle to it.
+            ///
+            /// If the set does not have an instance of `T`, or if it is shared, [`None`] is
+            /// returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(String::from("a"));
+            /// set.get_mut::<String>().unwrap().push('b');
+            ///
+            /// let shared = set.get_arc::<String>();
+            /// assert_eq!(set.get_mut::<String>(), None);
+            /// ```
+            #[must_use]
+            pub fn get_mut<T>(&mut self) -> Option<&mut T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let arc = self.inner.get_mut(&TypeId::of::<T>())?;
+                let value = Arc::get_mut(arc)?;
+
+                // Sanity check
+                debug_assert_eq!(Any::type_id(value), TypeId::of::<T>());
+
+                let ptr = (value as *mut dyn Any).cast::<T>();
+
+                Some(unsafe { &mut *ptr })
+            }
+
+            /// Returns a new handle to an instance of `T`.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use std::sync::Arc;
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            ///
+            /// let a = set.get_arc::<&str>().unwrap();
+            /// assert_eq!(*a, "a");
+            /// assert_eq!(Arc::strong_count(&a), 2);
+            /// ```
+            #[must_use]
+            pub fn get_arc<T>(&self) -> Option<Arc<T>>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.inner
+                    .get(&TypeId::of::<T>())
+                    .map(|arc| Self::downcast(Arc::clone(arc)))
+            }
+
+            /// Returns a weak handle to an instance of `T`.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            ///
+            /// let a = set.downgrade::<&str>().unwrap();
+            /// assert_eq!(a.upgrade().as_deref(), Some(&"a"));
+            ///
+            /// set.clear();
+            /// assert_eq!(a.upgrade(), None);
+            /// ```
+            #[must_use]
+            pub fn downgrade<T>(&self) -> Option<Weak<T>>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.get_arc::<T>().as_ref().map(Arc::downgrade)
+            }
+
+            /// Insert an instance of type `T` into the set.
+            ///
+            /// Returns the replaced handle, if any.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.insert("a"), None);
+            /// assert_eq!(set.insert("b").as_deref(), Some(&"a"));
+            /// ```
+            pub fn insert<T>(&mut self, value: T) -> Option<Arc<T>>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.insert_arc(Arc::new(value))
+            }
+
+            /// Insert a handle to an instance of type `T` into the set, e.g. one returned by
+            /// [`get_arc`](Self::get_arc) on another set, so that both sets share the value.
+            ///
+            /// Returns the replaced handle, if any.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use std::sync::Arc;
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut registry = ", stringify!($name), "::new();")]
+            /// registry.insert(String::from("service"));
+            ///
+            #[doc = concat!("let mut request = ", stringify!($name), "::new();")]
+            /// request.insert_arc(registry.get_arc::<String>().unwrap());
+            ///
+            /// assert!(std::ptr::eq(
+            ///     registry.get::<String>().unwrap(),
+            ///     request.get::<String>().unwrap(),
+            /// ));
+            /// ```
+            pub fn insert_arc<T>(&mut self, value: Arc<T>) -> Option<Arc<T>>
+            where
+                T: Any $(+ $bound)*,
+            {
+                #[cfg(debug_assertions)]
+                self.debug_type_names.insert(TypeId::of::<T>(), ::core::any::type_name::<T>());
+
+                self.inner
+                    .insert(TypeId::of::<T>(), value)
+                    .map(Self::downcast)
+            }
+
+            /// Remove and return the handle to an instance of type `T` from the set.
+            ///
+            /// If the set did not have this type present, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.remove::<&str>().as_deref(), Some(&"a"));
+            /// assert_eq!(set.remove::<&str>(), None);
+            /// ```
+            pub fn remove<T>(&mut self) -> Option<Arc<T>>
+            where
+                T: Any $(+ $bound)*,
+            {
+                #[cfg(debug_assertions)]
+                self.debug_type_names.remove(&TypeId::of::<T>());
+
+                self.inner.remove(&TypeId::of::<T>()).map(Self::downcast)
+            }
+
+            /// Gets an iterator over the [`TypeId`]s of stored elements, in arbitrary order.
+            pub fn type_ids(&self) -> impl Iterator<Item = &TypeId> {
+                self.inner.keys()
+            }
+
+            fn downcast<T: Any>(arc: Arc<dyn Any $(+ $bound)*>) -> Arc<T> {
+                // Sanity check
+                debug_assert_eq!(Any::type_id(&*arc), TypeId::of::<T>());
+
+                let ptr = (Arc::into_raw(arc) as *const dyn Any).cast::<T>();
+
+                unsafe { Arc::from_raw(ptr) }
+            }
+        }
+
+        impl ::core::default::Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        /// Cloning the set clones the handles, the values are shared.
+        impl ::core::clone::Clone for $name {
+            fn clone(&self) -> Self {
+                Self {
+                    inner: self.inner.clone(),
+                    #[cfg(debug_assertions)]
+                    debug_type_names: self.debug_type_names.clone(),
+                }
+            }
+        }
+
+        /// Shows the type names of the values, or their [`TypeId`] in release mode.
+        impl ::core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                #[cfg(debug_assertions)]
+                return f.debug_set().entries(self.debug_type_names.values()).finish();
+
+                #[cfg(not(debug_assertions))]
+                return f.debug_set().entries(self.inner.keys()).finish();
+            }
+        }
+    };
+}
+
+impl_erased_arc_set! {
+    /// A set of erased types, each stored behind an [`Arc`].
+    ///
+    /// Values are returned as `Arc<T>` or [`Weak<T>`] handles, and sets can share values without
+    /// cloning them. See `ErasedSyncArcSet` to share them between
+    /// threads.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedArcSet;
+    ///
+    /// # #[derive(Debug, PartialEq)]
+    /// struct Database(&'static str);
+    ///
+    /// let mut registry = ErasedArcSet::new();
+    /// registry.insert(Database("postgres://localhost"));
+    ///
+    /// let mut request = ErasedArcSet::new();
+    /// request.insert_arc(registry.get_arc::<Database>().unwrap());
+    ///
+    /// let database = request.get_arc::<Database>().unwrap();
+    /// assert_eq!(*database, Database("postgres://localhost"));
+    /// ```
+    pub struct ErasedArcSet: Any;
+}
+
+#[cfg(feature = "sync")]
+impl_erased_arc_set! {
+    /// Like [`ErasedArcSet`] but with a [`Send`] + [`Sync`] bound.
+    pub struct ErasedSyncArcSet: Any + Send + Sync;
+}
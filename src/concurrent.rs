@@ -0,0 +1,386 @@
+use ::alloc::collections::BTreeMap;
+use ::alloc::sync::Arc;
+use ::core::any::{Any, TypeId};
+use ::core::fmt;
+use ::core::hash::{Hash, Hasher};
+use ::core::ops::{Deref, DerefMut};
+use ::core::sync::atomic::Ordering;
+use ::std::sync::PoisonError;
+
+#[cfg(loom)]
+use ::loom::sync::{atomic::AtomicUsize, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(loom))]
+use ::std::sync::{atomic::AtomicUsize, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::storage::TypeIdHasher;
+use crate::SmallBox;
+
+/// The number of shards of an [`ErasedConcurrentSet`].
+const SHARDS: usize = 16;
+
+/// The lock of a single type.
+///
+/// Slots are only dropped with the set, a slot can therefore be borrowed for the lifetime of the
+/// set once its shard is unlocked. It is kept in an `Arc` rather than a `Box` so that moving it
+/// within its shard does not invalidate these borrows.
+type Slot = RwLock<Option<SmallBox<dyn Any + Send + Sync>>>;
+
+type Shard = RwLock<BTreeMap<TypeId, Arc<Slot>>>;
+
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A set of erased types that can be shared between threads and mutated through `&self`.
+///
+/// Each type is stored behind its own lock, [`read`](Self::read) and [`write`](Self::write)
+/// return guards that only lock the value of one type, so threads accessing distinct types do
+/// not block each other. The map of these locks is sharded by [`TypeId`], and a shard is only
+/// locked briefly, to look up a type or to add it on its first insertion.
+///
+/// Like [`RwLock`](::std::sync::RwLock), accessing a type while the current thread holds a
+/// conflicting guard on that same type deadlocks. Poisoning is ignored: a panic while a
+/// [`WriteGuard`] is held leaves the value as it was at that point.
+///
+/// ## Example
+///
+/// ```
+/// use std::thread;
+/// use erased_set::ErasedConcurrentSet;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Clicks(u32);
+/// #[derive(Debug, PartialEq)]
+/// struct Keys(u32);
+///
+/// let set = ErasedConcurrentSet::new();
+/// set.insert(Clicks(0));
+/// set.insert(Keys(0));
+///
+/// thread::scope(|s| {
+///     s.spawn(|| set.write::<Clicks>().unwrap().0 += 1);
+///     s.spawn(|| set.write::<Keys>().unwrap().0 += 1);
+/// });
+///
+/// assert_eq!(*set.read::<Clicks>().unwrap(), Clicks(1));
+/// assert_eq!(set.remove::<Keys>(), Some(Keys(1)));
+/// ```
+pub struct ErasedConcurrentSet {
+    shards: [Shard; SHARDS],
+    len: AtomicUsize,
+}
+
+impl ErasedConcurrentSet {
+    /// Creates an empty [`ErasedConcurrentSet`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedConcurrentSet;
+    ///
+    /// let set = ErasedConcurrentSet::new();
+    /// ```
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            shards: ::core::array::from_fn(|_| RwLock::new(BTreeMap::new())),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns `true` if the set contains no instances of any type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedConcurrentSet;
+    ///
+    /// let set = ErasedConcurrentSet::new();
+    /// assert!(set.is_empty());
+    /// set.insert(1_u8);
+    /// assert!(!set.is_empty());
+    /// ```
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of types in the set.
+    ///
+    /// The result may already be outdated if other threads insert or remove values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedConcurrentSet;
+    ///
+    /// let set = ErasedConcurrentSet::new();
+    /// set.insert(1_u8);
+    /// set.insert("a");
+    /// assert_eq!(set.len(), 2);
+    /// ```
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if the set contains an instance of `T`.
+    ///
+    /// Blocks while another thread holds a [`WriteGuard`] on `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedConcurrentSet;
+    ///
+    /// let set = ErasedConcurrentSet::new();
+    /// set.insert("a");
+    /// assert!(set.contains::<&str>());
+    /// assert!(!set.contains::<u8>());
+    /// ```
+    #[must_use]
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Any,
+    {
+        self.slot::<T>().map_or(false, |slot| read(slot).is_some())
+    }
+
+    /// Locks the instance of type `T` for reading.
+    ///
+    /// Returns [`None`] if the set does not contain an instance of `T`, and blocks while another
+    /// thread holds a [`WriteGuard`] on `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedConcurrentSet;
+    ///
+    /// let set = ErasedConcurrentSet::new();
+    /// set.insert("a");
+    /// assert_eq!(set.read::<&str>().as_deref(), Some(&"a"));
+    /// assert!(set.read::<u8>().is_none());
+    /// ```
+    #[must_use]
+    pub fn read<T>(&self) -> Option<ReadGuard<'_, T>>
+    where
+        T: Any + Send + Sync,
+    {
+        let guard = read(self.slot::<T>()?);
+        let boxed_any = guard.as_ref()?;
+
+        // Sanity check
+        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+        let ptr = (boxed_any.as_ref() as *const dyn Any).cast::<T>();
+
+        Some(ReadGuard { ptr, _guard: guard })
+    }
+
+    /// Locks the instance of type `T` for writing.
+    ///
+    /// Returns [`None`] if the set does not contain an instance of `T`, and blocks while another
+    /// thread holds a [`ReadGuard`] or a [`WriteGuard`] on `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedConcurrentSet;
+    ///
+    /// let set = ErasedConcurrentSet::new();
+    /// set.insert(String::from("a"));
+    /// set.write::<String>().unwrap().push('b');
+    /// assert_eq!(set.read::<String>().as_deref().map(String::as_str), Some("ab"));
+    /// ```
+    #[must_use]
+    pub fn write<T>(&self) -> Option<WriteGuard<'_, T>>
+    where
+        T: Any + Send + Sync,
+    {
+        let mut guard = write(self.slot::<T>()?);
+        let boxed_any = guard.as_mut()?;
+
+        // Sanity check
+        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+        let ptr = (boxed_any.as_mut() as *mut dyn Any).cast::<T>();
+
+        Some(WriteGuard { ptr, _guard: guard })
+    }
+
+    /// Insert an instance of type `T` into the set.
+    ///
+    /// Returns the replaced value, if any. Blocks while another thread holds a [`ReadGuard`] or a
+    /// [`WriteGuard`] on `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedConcurrentSet;
+    ///
+    /// let set = ErasedConcurrentSet::new();
+    /// assert_eq!(set.insert("a"), None);
+    /// assert_eq!(set.insert("b"), Some("a"));
+    /// ```
+    pub fn insert<T>(&self, value: T) -> Option<T>
+    where
+        T: Any + Send + Sync,
+    {
+        let vtable = crate::impl_erased_set! { @vtable T [+ Send + Sync] [] };
+
+        let mut guard = write(self.slot_or_insert::<T>());
+        let previous = guard.replace(unsafe { SmallBox::new(value, vtable) });
+
+        if previous.is_none() {
+            self.len.fetch_add(1, Ordering::AcqRel);
+        }
+
+        drop(guard);
+
+        previous.map(|boxed_any| {
+            // Sanity check
+            debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+            unsafe { boxed_any.into_inner_unchecked() }
+        })
+    }
+
+    /// Remove and return an instance of type `T` from the set.
+    ///
+    /// If the set did not have this type present, [`None`] is returned. Blocks while another
+    /// thread holds a [`ReadGuard`] or a [`WriteGuard`] on `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedConcurrentSet;
+    ///
+    /// let set = ErasedConcurrentSet::new();
+    /// set.insert("a");
+    /// assert_eq!(set.remove::<&str>(), Some("a"));
+    /// assert_eq!(set.remove::<&str>(), None);
+    /// ```
+    pub fn remove<T>(&self) -> Option<T>
+    where
+        T: Any + Send + Sync,
+    {
+        let mut guard = write(self.slot::<T>()?);
+        let previous = guard.take();
+
+        if previous.is_some() {
+            self.len.fetch_sub(1, Ordering::AcqRel);
+        }
+
+        drop(guard);
+
+        previous.map(|boxed_any| {
+            // Sanity check
+            debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+            unsafe { boxed_any.into_inner_unchecked() }
+        })
+    }
+
+    fn shard(&self, type_id: &TypeId) -> &Shard {
+        let mut hasher = TypeIdHasher::default();
+        type_id.hash(&mut hasher);
+
+        let index = hasher.finish() % SHARDS as u64;
+
+        &self.shards[index as usize]
+    }
+
+    fn slot<T: Any>(&self) -> Option<&Slot> {
+        let type_id = TypeId::of::<T>();
+        let ptr = Arc::as_ptr(read(self.shard(&type_id)).get(&type_id)?);
+
+        // The slot outlives the shard guard, see `Slot`.
+        Some(unsafe { &*ptr })
+    }
+
+    fn slot_or_insert<T: Any>(&self) -> &Slot {
+        if let Some(slot) = self.slot::<T>() {
+            return slot;
+        }
+
+        let type_id = TypeId::of::<T>();
+        let ptr = Arc::as_ptr(
+            write(self.shard(&type_id))
+                .entry(type_id)
+                .or_insert_with(|| Arc::new(RwLock::new(None))),
+        );
+
+        // The slot outlives the shard guard, see `Slot`.
+        unsafe { &*ptr }
+    }
+}
+
+impl Default for ErasedConcurrentSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ErasedConcurrentSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErasedConcurrentSet")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A shared borrow of a value of an [`ErasedConcurrentSet`].
+///
+/// This `struct` is created by [`ErasedConcurrentSet::read`], the value is unlocked when it is
+/// dropped.
+pub struct ReadGuard<'a, T> {
+    ptr: *const T,
+    _guard: RwLockReadGuard<'a, Option<SmallBox<dyn Any + Send + Sync>>>,
+}
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ptr }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+/// A mutable borrow of a value of an [`ErasedConcurrentSet`].
+///
+/// This `struct` is created by [`ErasedConcurrentSet::write`], the value is unlocked when it is
+/// dropped.
+pub struct WriteGuard<'a, T> {
+    ptr: *mut T,
+    _guard: RwLockWriteGuard<'a, Option<SmallBox<dyn Any + Send + Sync>>>,
+}
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ptr }
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for WriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
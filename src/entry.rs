@@ -0,0 +1,286 @@
+use crate::storage::{
+    BTreeStorage, OccupiedStorageEntry, Storage, StorageEntry, VacantStorageEntry,
+};
+use crate::SmallBox;
+use ::alloc::collections::BTreeMap;
+use ::core::any::{Any, TypeId};
+use ::core::marker::PhantomData;
+
+/// A view into a single type in an erased set, which may either be vacant or occupied.
+///
+/// This `enum` is constructed from the `entry` method on the erased sets.
+pub enum Entry<'a, T, S: Storage + 'a = BTreeStorage<SmallBox<dyn Any>>> {
+    /// An occupied entry.
+    Occupied(OccupiedEntry<'a, T, S>),
+    /// A vacant entry.
+    Vacant(VacantEntry<'a, T, S>),
+}
+
+/// A view into an occupied entry in an erased set. It is part of the [`Entry`] enum.
+pub struct OccupiedEntry<'a, T, S: Storage + 'a = BTreeStorage<SmallBox<dyn Any>>> {
+    inner: S::OccupiedEntry<'a>,
+    debug_type_names: Option<&'a mut BTreeMap<TypeId, &'static str>>,
+    marker: PhantomData<&'a mut T>,
+}
+
+/// A view into a vacant entry in an erased set. It is part of the [`Entry`] enum.
+pub struct VacantEntry<'a, T, S: Storage + 'a = BTreeStorage<SmallBox<dyn Any>>> {
+    inner: S::VacantEntry<'a>,
+    debug_type_names: Option<&'a mut BTreeMap<TypeId, &'static str>>,
+    into_boxed: fn(T) -> S::Value,
+}
+
+impl<'a, T, B, S> Entry<'a, T, S>
+where
+    T: Any,
+    B: ?Sized + Any,
+    S: Storage<Value = SmallBox<B>> + 'a,
+{
+    /// # Safety
+    ///
+    /// `entry` must be the entry of `TypeId::of::<T>()` in a storage that holds instances of
+    /// their own type, i.e. an occupied `entry` must hold an instance of `T`.
+    #[doc(hidden)]
+    pub unsafe fn new(
+        entry: StorageEntry<S::OccupiedEntry<'a>, S::VacantEntry<'a>>,
+        debug_type_names: Option<&'a mut BTreeMap<TypeId, &'static str>>,
+        into_boxed: fn(T) -> SmallBox<B>,
+    ) -> Self {
+        match entry {
+            StorageEntry::Occupied(inner) => Self::Occupied(OccupiedEntry {
+                inner,
+                debug_type_names,
+                marker: PhantomData,
+            }),
+            StorageEntry::Vacant(inner) => Self::Vacant(VacantEntry {
+                inner,
+                debug_type_names,
+                into_boxed,
+            }),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting `default` if empty, and returns a mutable
+    /// reference to the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedSet;
+    ///
+    /// let mut set = ErasedSet::new();
+    /// *set.entry::<u32>().or_insert(1) += 10;
+    /// assert_eq!(set.get::<u32>(), Some(&11));
+    /// ```
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of `f` if empty, and returns a
+    /// mutable reference to the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedSet;
+    ///
+    /// let mut set = ErasedSet::new();
+    /// set.entry::<String>().or_insert_with(|| String::from("abc")).push('d');
+    /// assert_eq!(set.get::<String>().map(String::as_str), Some("abcd"));
+    /// ```
+    pub fn or_insert_with(self, f: impl FnOnce() -> T) -> &'a mut T {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the default value if empty, and returns a
+    /// mutable reference to the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedSet;
+    ///
+    /// let mut set = ErasedSet::new();
+    /// set.entry::<Vec<u32>>().or_default().push(1);
+    /// assert_eq!(set.get::<Vec<u32>>(), Some(&vec![1]));
+    /// ```
+    pub fn or_default(self) -> &'a mut T
+    where
+        T: Default,
+    {
+        self.or_insert_with(T::default)
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any potential inserts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedSet;
+    ///
+    /// let mut set = ErasedSet::new();
+    /// set.entry::<u32>().and_modify(|x| *x += 1).or_insert(42);
+    /// assert_eq!(set.get::<u32>(), Some(&42));
+    /// set.entry::<u32>().and_modify(|x| *x += 1).or_insert(42);
+    /// assert_eq!(set.get::<u32>(), Some(&43));
+    /// ```
+    #[must_use]
+    pub fn and_modify(mut self, f: impl FnOnce(&mut T)) -> Self {
+        if let Self::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+
+        self
+    }
+}
+
+impl<'a, T, B, S> OccupiedEntry<'a, T, S>
+where
+    T: Any,
+    B: ?Sized + Any,
+    S: Storage<Value = SmallBox<B>> + 'a,
+{
+    /// Gets a reference to the value in the entry.
+    #[must_use]
+    pub fn get(&self) -> &T {
+        let value: &B = self.inner.get().as_ref();
+
+        // Sanity check
+        debug_assert_eq!(Any::type_id(value), TypeId::of::<T>());
+
+        let ptr = (value as *const B).cast::<T>();
+
+        unsafe { &*ptr }
+    }
+
+    /// Gets a mutable reference to the value in the entry.
+    ///
+    /// If you need a reference to the `OccupiedEntry` which may outlive the destruction of the
+    /// [`Entry`] value, see [`into_mut`](Self::into_mut).
+    #[must_use]
+    pub fn get_mut(&mut self) -> &mut T {
+        let value: &mut B = self.inner.get_mut().as_mut();
+
+        // Sanity check
+        debug_assert_eq!(Any::type_id(value), TypeId::of::<T>());
+
+        let ptr = (value as *mut B).cast::<T>();
+
+        unsafe { &mut *ptr }
+    }
+
+    /// Converts the entry into a mutable reference to its value.
+    ///
+    /// If you need multiple references to the `OccupiedEntry`, see
+    /// [`get_mut`](Self::get_mut).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::{Entry, ErasedSet};
+    ///
+    /// let mut set = ErasedSet::new();
+    /// set.insert(1_u32);
+    /// if let Entry::Occupied(entry) = set.entry::<u32>() {
+    ///     *entry.into_mut() += 1;
+    /// }
+    /// assert_eq!(set.get::<u32>(), Some(&2));
+    /// ```
+    #[must_use]
+    pub fn into_mut(self) -> &'a mut T {
+        let value: &'a mut B = self.inner.into_mut().as_mut();
+
+        // Sanity check
+        debug_assert_eq!(Any::type_id(value), TypeId::of::<T>());
+
+        let ptr = (value as *mut B).cast::<T>();
+
+        unsafe { &mut *ptr }
+    }
+
+    /// Sets the value of the entry and returns the entry's old value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::{Entry, ErasedSet};
+    ///
+    /// let mut set = ErasedSet::new();
+    /// set.insert("a");
+    /// if let Entry::Occupied(mut entry) = set.entry::<&str>() {
+    ///     assert_eq!(entry.insert("b"), "a");
+    /// }
+    /// assert_eq!(set.get::<&str>(), Some(&"b"));
+    /// ```
+    pub fn insert(&mut self, value: T) -> T {
+        ::core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Takes the value out of the entry, and returns it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::{Entry, ErasedSet};
+    ///
+    /// let mut set = ErasedSet::new();
+    /// set.insert("a");
+    /// if let Entry::Occupied(entry) = set.entry::<&str>() {
+    ///     assert_eq!(entry.remove(), "a");
+    /// }
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn remove(self) -> T {
+        if let Some(debug_type_names) = self.debug_type_names {
+            debug_type_names.remove(&TypeId::of::<T>());
+        }
+
+        let boxed: SmallBox<B> = self.inner.remove();
+
+        // Sanity check
+        debug_assert_eq!(Any::type_id(boxed.as_ref()), TypeId::of::<T>());
+
+        unsafe { boxed.into_inner_unchecked() }
+    }
+}
+
+impl<'a, T, B, S> VacantEntry<'a, T, S>
+where
+    T: Any,
+    B: ?Sized + Any,
+    S: Storage<Value = SmallBox<B>> + 'a,
+{
+    /// Sets the value of the entry, and returns a mutable reference to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::{Entry, ErasedSet};
+    ///
+    /// let mut set = ErasedSet::new();
+    /// if let Entry::Vacant(entry) = set.entry::<&str>() {
+    ///     assert_eq!(entry.insert("a"), &mut "a");
+    /// }
+    /// assert_eq!(set.get::<&str>(), Some(&"a"));
+    /// ```
+    pub fn insert(self, value: T) -> &'a mut T {
+        if let Some(debug_type_names) = self.debug_type_names {
+            debug_type_names.insert(TypeId::of::<T>(), ::core::any::type_name::<T>());
+        }
+
+        let value: &'a mut B = self.inner.insert((self.into_boxed)(value)).as_mut();
+
+        // Sanity check
+        debug_assert_eq!(Any::type_id(value), TypeId::of::<T>());
+
+        let ptr = (value as *mut B).cast::<T>();
+
+        unsafe { &mut *ptr }
+    }
+}
@@ -0,0 +1,304 @@
+use ::alloc::collections::{btree_map, BTreeMap};
+use ::alloc::rc::Rc;
+use ::core::any::{Any, TypeId};
+use ::core::cell::RefCell;
+use ::core::fmt;
+
+/// A set of erased types where values can be inserted through `&self`, similar to
+/// [`elsa::FrozenMap`](https://docs.rs/elsa/latest/elsa/map/struct.FrozenMap.html).
+///
+/// [`insert`](Self::insert) only adds types that are absent, references returned by `&self`
+/// methods therefore stay valid while other types are inserted. Replacing or removing a value
+/// requires `&mut self`, which statically ends these borrows.
+///
+/// ## Example
+///
+/// ```
+/// use erased_set::FrozenErasedSet;
+///
+/// # #[derive(Debug, PartialEq)]
+/// struct Name(String);
+/// # #[derive(Debug, PartialEq)]
+/// struct Greeting(String);
+///
+/// let mut set = FrozenErasedSet::new();
+/// let name = set.insert(Name(String::from("world")));
+/// let greeting = set.insert(Greeting(format!("hello {}", name.0)));
+///
+/// assert_eq!(name, &Name(String::from("world")));
+/// assert_eq!(greeting, &Greeting(String::from("hello world")));
+///
+/// assert_eq!(set.remove::<Name>(), Some(Name(String::from("world"))));
+/// ```
+pub struct FrozenErasedSet {
+    // Values are kept in an `Rc` rather than a `Box` so that moving them within the map does not
+    // invalidate the references handed out by `&self` methods. They are never shared.
+    inner: RefCell<BTreeMap<TypeId, Rc<dyn Any>>>,
+}
+
+impl FrozenErasedSet {
+    /// Creates an empty [`FrozenErasedSet`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let set = FrozenErasedSet::new();
+    /// ```
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            inner: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns `true` if the set contains no instances of any type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let set = FrozenErasedSet::new();
+    /// assert!(set.is_empty());
+    /// set.insert(1_u8);
+    /// assert!(!set.is_empty());
+    /// ```
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inner.borrow().is_empty()
+    }
+
+    /// Returns the number of types in the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let set = FrozenErasedSet::new();
+    /// set.insert(1_u8);
+    /// set.insert("a");
+    /// assert_eq!(set.len(), 2);
+    /// ```
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.inner.borrow().len()
+    }
+
+    /// Clears the set, removing all values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let mut set = FrozenErasedSet::new();
+    /// set.insert(1_u8);
+    /// set.clear();
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.inner.get_mut().clear();
+    }
+
+    /// Returns `true` if the set contains an instance of `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let set = FrozenErasedSet::new();
+    /// set.insert("a");
+    /// assert!(set.contains::<&str>());
+    /// assert!(!set.contains::<u8>());
+    /// ```
+    #[must_use]
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Any,
+    {
+        self.inner.borrow().contains_key(&TypeId::of::<T>())
+    }
+
+    /// Returns a reference to an instance of `T`.
+    ///
+    /// If the set does not have an instance of `T`, [`None`] is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let set = FrozenErasedSet::new();
+    /// set.insert("a");
+    /// assert_eq!(set.get::<&str>(), Some(&"a"));
+    /// assert_eq!(set.get::<u8>(), None);
+    /// ```
+    #[must_use]
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: Any,
+    {
+        let inner = self.inner.borrow();
+        let value = inner.get(&TypeId::of::<T>())?;
+
+        // Sanity check
+        debug_assert_eq!(Any::type_id(&**value), TypeId::of::<T>());
+
+        let ptr = Rc::as_ptr(value).cast::<T>();
+
+        Some(unsafe { &*ptr })
+    }
+
+    /// Returns a mutable reference to an instance of `T`.
+    ///
+    /// If the set does not have an instance of `T`, [`None`] is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let mut set = FrozenErasedSet::new();
+    /// set.insert(String::from("a"));
+    /// set.get_mut::<String>().unwrap().push('b');
+    /// assert_eq!(set.get::<String>().map(String::as_str), Some("ab"));
+    /// ```
+    #[must_use]
+    pub fn get_mut<T>(&mut self) -> Option<&mut T>
+    where
+        T: Any,
+    {
+        let value = self.inner.get_mut().get_mut(&TypeId::of::<T>())?;
+
+        Rc::get_mut(value)
+            .expect("values of a `FrozenErasedSet` are never shared")
+            .downcast_mut()
+    }
+
+    /// Insert an instance of type `T` into the set if it is not present, then returns a
+    /// reference to the value in the set.
+    ///
+    /// If the set already had this type present, `value` is dropped, see
+    /// [`replace`](Self::replace) to replace it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let set = FrozenErasedSet::new();
+    /// let a = set.insert("a");
+    /// assert_eq!(set.insert("b"), &"a");
+    /// assert_eq!(a, &"a");
+    /// ```
+    pub fn insert<T>(&self, value: T) -> &T
+    where
+        T: Any,
+    {
+        let ptr = match self.inner.borrow_mut().entry(TypeId::of::<T>()) {
+            btree_map::Entry::Occupied(entry) => Rc::as_ptr(entry.get()),
+            btree_map::Entry::Vacant(entry) => Rc::as_ptr(entry.insert(Rc::new(value))),
+        };
+
+        // Sanity check
+        debug_assert_eq!(unsafe { Any::type_id(&*ptr) }, TypeId::of::<T>());
+
+        unsafe { &*ptr.cast::<T>() }
+    }
+
+    /// Inserts the value returned by `f` into the set if `T` is not present, then returns a
+    /// reference to the value in the set.
+    ///
+    /// The set is not borrowed while `f` runs, `f` may therefore insert other types.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let set = FrozenErasedSet::new();
+    /// let len = set.get_or_insert_with(|| set.get_or_insert_with(|| "abc").len());
+    /// assert_eq!(len, &3);
+    /// ```
+    pub fn get_or_insert_with<T>(&self, f: impl FnOnce() -> T) -> &T
+    where
+        T: Any,
+    {
+        match self.get::<T>() {
+            Some(value) => value,
+            None => self.insert(f()),
+        }
+    }
+
+    /// Insert an instance of type `T` into the set, replacing any previous instance.
+    ///
+    /// Returns the replaced value, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let mut set = FrozenErasedSet::new();
+    /// assert_eq!(set.replace("a"), None);
+    /// assert_eq!(set.replace("b"), Some("a"));
+    /// ```
+    pub fn replace<T>(&mut self, value: T) -> Option<T>
+    where
+        T: Any,
+    {
+        self.inner
+            .get_mut()
+            .insert(TypeId::of::<T>(), Rc::new(value))
+            .map(Self::unwrap)
+    }
+
+    /// Remove and return an instance of type `T` from the set.
+    ///
+    /// If the set did not have this type present, [`None`] is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::FrozenErasedSet;
+    ///
+    /// let mut set = FrozenErasedSet::new();
+    /// set.insert("a");
+    /// assert_eq!(set.remove::<&str>(), Some("a"));
+    /// assert_eq!(set.remove::<&str>(), None);
+    /// ```
+    pub fn remove<T>(&mut self) -> Option<T>
+    where
+        T: Any,
+    {
+        self.inner
+            .get_mut()
+            .remove(&TypeId::of::<T>())
+            .map(Self::unwrap)
+    }
+
+    fn unwrap<T: Any>(value: Rc<dyn Any>) -> T {
+        match value.downcast().map(Rc::try_unwrap) {
+            Ok(Ok(value)) => value,
+            _ => unreachable!("values of a `FrozenErasedSet` are never shared"),
+        }
+    }
+}
+
+impl Default for FrozenErasedSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for FrozenErasedSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrozenErasedSet")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,512 @@
+use ::alloc::collections::VecDeque;
+use ::alloc::vec::Vec;
+use ::core::any::{Any, TypeId};
+use ::core::fmt;
+
+use crate::storage::Storage;
+use crate::SmallBox;
+
+/// The value a type had before a mutation, [`None`] if it was absent.
+struct Change<B: ?Sized + 'static> {
+    type_id: TypeId,
+    type_name: &'static str,
+    value: Option<SmallBox<B>>,
+}
+
+macro_rules! impl_erased_history_set {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $(+ $bound:ident)*;
+
+        $inner:ident
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            set: crate::$inner,
+            // The mutations since the last checkpoint.
+            pending: Vec<Change<dyn Any $(+ $bound)*>>,
+            undo: VecDeque<Vec<Change<dyn Any $(+ $bound)*>>>,
+            redo: Vec<Vec<Change<dyn Any $(+ $bound)*>>>,
+            depth: usize,
+        }
+
+        impl $name {
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`] keeping any number of checkpoints.")]
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// ```
+            #[must_use]
+            pub fn new() -> Self {
+                Self::with_depth(usize::MAX)
+            }
+
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`] keeping at most `depth` checkpoints.")]
+            ///
+            /// The oldest checkpoint is dropped when a new one exceeds the depth.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::with_depth(1);")]
+            /// set.insert(1_u8);
+            /// set.checkpoint();
+            /// set.insert(2_u8);
+            /// set.checkpoint();
+            ///
+            /// assert!(set.undo());
+            /// assert!(!set.undo());
+            /// assert_eq!(set.get::<u8>(), Some(&1));
+            /// ```
+            #[must_use]
+            pub fn with_depth(depth: usize) -> Self {
+                Self {
+                    set: crate::$inner::new(),
+                    pending: Vec::new(),
+                    undo: VecDeque::new(),
+                    redo: Vec::new(),
+                    depth,
+                }
+            }
+
+            /// Returns `true` if the set contains no instances of any type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert!(set.is_empty());
+            /// ```
+            #[must_use]
+            pub fn is_empty(&self) -> bool {
+                self.set.is_empty()
+            }
+
+            /// Returns the number of types in the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.insert("a");
+            /// assert_eq!(set.len(), 2);
+            /// ```
+            #[must_use]
+            pub fn len(&self) -> usize {
+                self.set.len()
+            }
+
+            /// Returns `true` if the set contains an instance of `T`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u8>());
+            /// ```
+            #[must_use]
+            pub fn contains<T>(&self) -> bool
+            where
+                T: Any,
+            {
+                self.set.contains::<T>()
+            }
+
+            /// Returns a reference to an instance of `T`.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// assert_eq!(set.get::<u8>(), None);
+            /// ```
+            #[must_use]
+            pub fn get<T>(&self) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.set.get::<T>()
+            }
+
+            /// Returns a mutable reference to an instance of `T`, recording a clone of the value
+            /// in the history first.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned and nothing is
+            /// recorded.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(String::from("a"));
+            /// set.checkpoint();
+            ///
+            /// set.get_mut::<String>().unwrap().push('b');
+            /// assert!(set.undo());
+            /// assert_eq!(set.get::<String>().map(String::as_str), Some("a"));
+            /// ```
+            #[must_use]
+            pub fn get_mut<T>(&mut self) -> Option<&mut T>
+            where
+                T: Any + Clone $(+ $bound)*,
+            {
+                let previous = self.set.get::<T>()?.clone();
+
+                self.record(Change {
+                    type_id: TypeId::of::<T>(),
+                    type_name: ::core::any::type_name::<T>(),
+                    value: Some(crate::$inner::small_box(previous)),
+                });
+
+                self.set.get_mut::<T>()
+            }
+
+            /// Insert an instance of type `T` into the set, moving the replaced value, if any,
+            /// into the history.
+            ///
+            /// Returns a reference to the replaced value, now in the history, if any.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.insert("a"), None);
+            /// set.checkpoint();
+            ///
+            /// assert_eq!(set.insert("b"), Some(&"a"));
+            /// assert!(set.undo());
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// ```
+            pub fn insert<T>(&mut self, value: T) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let type_id = TypeId::of::<T>();
+                let type_name = ::core::any::type_name::<T>();
+                let value = self.swap(type_id, type_name, Some(crate::$inner::small_box(value)));
+
+                self.record(Change { type_id, type_name, value });
+                self.recorded::<T>()
+            }
+
+            /// Remove the instance of type `T` from the set, moving it into the history.
+            ///
+            /// Returns a reference to the removed value, now in the history. If the set did not
+            /// have this type present, [`None`] is returned and nothing is recorded.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// set.checkpoint();
+            ///
+            /// assert_eq!(set.remove::<&str>(), Some(&"a"));
+            /// assert_eq!(set.remove::<&str>(), None);
+            /// assert!(set.undo());
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// ```
+            pub fn remove<T>(&mut self) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let type_id = TypeId::of::<T>();
+                let type_name = ::core::any::type_name::<T>();
+                let value = self.swap(type_id, type_name, None)?;
+
+                self.record(Change { type_id, type_name, value: Some(value) });
+                self.recorded::<T>()
+            }
+
+            /// Gets an iterator over the [`TypeId`]s of stored elements, in arbitrary order.
+            pub fn type_ids(&self) -> impl Iterator<Item = &TypeId> {
+                self.set.type_ids()
+            }
+
+            /// Marks the current state as a checkpoint, [`undo`](Self::undo) reverts the
+            /// mutations made since.
+            ///
+            /// Does nothing if the set was not mutated since the last checkpoint.
+            pub fn checkpoint(&mut self) {
+                if self.pending.is_empty() {
+                    return;
+                }
+
+                let changes = ::core::mem::take(&mut self.pending);
+                self.push_undo(changes);
+            }
+
+            /// Reverts the mutations made since the previous checkpoint, marking the current
+            /// state as a checkpoint first.
+            ///
+            /// Returns `false` if there is nothing to undo.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.checkpoint();
+            ///
+            /// set.insert(2_u8);
+            /// set.insert("a");
+            ///
+            /// assert!(set.undo());
+            /// assert_eq!(set.get::<u8>(), Some(&1));
+            /// assert!(!set.contains::<&str>());
+            ///
+            /// assert!(set.undo());
+            /// assert!(set.is_empty());
+            /// assert!(!set.undo());
+            /// ```
+            pub fn undo(&mut self) -> bool {
+                self.checkpoint();
+
+                match self.undo.pop_back() {
+                    Some(changes) => {
+                        let changes = self.revert(changes);
+                        self.redo.push(changes);
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            /// Applies again the mutations reverted by the last [`undo`](Self::undo).
+            ///
+            /// Returns `false` if there is nothing to redo, any mutation since the last undo
+            /// discards the mutations to redo.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.undo();
+            /// assert!(set.is_empty());
+            ///
+            /// assert!(set.redo());
+            /// assert_eq!(set.get::<u8>(), Some(&1));
+            /// assert!(!set.redo());
+            /// ```
+            pub fn redo(&mut self) -> bool {
+                match self.redo.pop() {
+                    Some(changes) => {
+                        let changes = self.revert(changes);
+                        self.push_undo(changes);
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            /// Returns `true` if [`undo`](Self::undo) would revert mutations.
+            #[must_use]
+            pub fn can_undo(&self) -> bool {
+                !self.pending.is_empty() || !self.undo.is_empty()
+            }
+
+            /// Returns `true` if [`redo`](Self::redo) would apply mutations.
+            #[must_use]
+            pub fn can_redo(&self) -> bool {
+                !self.redo.is_empty()
+            }
+
+            /// Drops the history, the current state can no longer be undone.
+            pub fn clear_history(&mut self) {
+                self.pending.clear();
+                self.undo.clear();
+                self.redo.clear();
+            }
+
+            #[doc = concat!("Returns the underlying [`", stringify!($inner), "`].")]
+            ///
+            /// It gives read access to the methods that this type does not forward, mutable
+            /// access is not given since it would bypass the history.
+            #[must_use]
+            pub fn as_set(&self) -> &crate::$inner {
+                &self.set
+            }
+
+            #[doc = concat!("Returns the underlying [`", stringify!($inner), "`], dropping the history.")]
+            #[must_use]
+            pub fn into_set(self) -> crate::$inner {
+                self.set
+            }
+
+            /// Stores `value` as the value of `type_id`, or removes it if [`None`], and returns
+            /// the previous value.
+            fn swap(
+                &mut self,
+                type_id: TypeId,
+                type_name: &'static str,
+                value: Option<SmallBox<dyn Any $(+ $bound)*>>,
+            ) -> Option<SmallBox<dyn Any $(+ $bound)*>> {
+                #[cfg(not(debug_assertions))]
+                let _ = type_name;
+
+                match value {
+                    Some(value) => {
+                        #[cfg(debug_assertions)]
+                        self.set.debug_type_names.insert(type_id, type_name);
+
+                        // The values of the history were stored under `type_id` in the set.
+                        unsafe { Storage::insert(self.set.inner.as_storage_mut(), type_id, value) }
+                    }
+                    None => {
+                        #[cfg(debug_assertions)]
+                        self.set.debug_type_names.remove(&type_id);
+
+                        self.set.inner.remove(&type_id)
+                    }
+                }
+            }
+
+            /// Restores the values of `changes`, latest first, and returns the changes restoring
+            /// the values they replaced.
+            fn revert(
+                &mut self,
+                changes: Vec<Change<dyn Any $(+ $bound)*>>,
+            ) -> Vec<Change<dyn Any $(+ $bound)*>> {
+                changes
+                    .into_iter()
+                    .rev()
+                    .map(|Change { type_id, type_name, value }| Change {
+                        type_id,
+                        type_name,
+                        value: self.swap(type_id, type_name, value),
+                    })
+                    .collect()
+            }
+
+            /// Returns a reference to the value recorded by the latest change, a `T`.
+            fn recorded<T: Any>(&self) -> Option<&T> {
+                let value = self.pending.last()?.value.as_ref()?;
+
+                // Sanity check
+                debug_assert_eq!(Any::type_id(value.as_ref()), TypeId::of::<T>());
+
+                let ptr = (value.as_ref() as *const dyn Any).cast::<T>();
+
+                Some(unsafe { &*ptr })
+            }
+
+            fn record(&mut self, change: Change<dyn Any $(+ $bound)*>) {
+                self.pending.push(change);
+                self.redo.clear();
+            }
+
+            fn push_undo(&mut self, changes: Vec<Change<dyn Any $(+ $bound)*>>) {
+                self.undo.push_back(changes);
+
+                if self.undo.len() > self.depth {
+                    self.undo.pop_front();
+                }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    .field("set", &self.set)
+                    .field("can_undo", &self.can_undo())
+                    .field("can_redo", &self.can_redo())
+                    .finish()
+            }
+        }
+    };
+}
+
+impl_erased_history_set! {
+    /// An [`ErasedSet`](crate::ErasedSet) keeping an undo and redo history of its mutations,
+    /// e.g. for the document of an editor.
+    ///
+    /// Insertions and removals move the previous values into the history, they do not need to
+    /// implement [`Clone`]. Only [`get_mut`](Self::get_mut) does, recording a clone of the value
+    /// before lending it. The mutations are grouped by [`checkpoint`](Self::checkpoint) into the
+    /// steps reverted by [`undo`](Self::undo).
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedHistorySet;
+    ///
+    /// // Neither type implements `Clone`.
+    /// # #[derive(Debug, PartialEq)]
+    /// struct Title(&'static str);
+    /// # #[derive(Debug, PartialEq)]
+    /// struct Body(&'static str);
+    ///
+    /// let mut document = ErasedHistorySet::new();
+    /// document.insert(Title("Draft"));
+    /// document.checkpoint();
+    ///
+    /// assert_eq!(document.insert(Title("Report")), Some(&Title("Draft")));
+    /// document.insert(Body("..."));
+    /// document.checkpoint();
+    ///
+    /// assert!(document.undo());
+    /// assert_eq!(document.get::<Title>(), Some(&Title("Draft")));
+    /// assert!(!document.contains::<Body>());
+    ///
+    /// assert!(document.redo());
+    /// assert_eq!(document.get::<Title>(), Some(&Title("Report")));
+    /// assert_eq!(document.get::<Body>(), Some(&Body("...")));
+    /// ```
+    pub struct ErasedHistorySet: Any;
+
+    ErasedSet
+}
+
+#[cfg(feature = "send")]
+impl_erased_history_set! {
+    /// Like [`ErasedHistorySet`] but with a [`Send`] bound.
+    pub struct ErasedSendHistorySet: Any + Send;
+
+    ErasedSendSet
+}
+
+#[cfg(feature = "sync")]
+impl_erased_history_set! {
+    /// Like [`ErasedHistorySet`] but with a [`Send`] + [`Sync`] bound.
+    pub struct ErasedSyncHistorySet: Any + Send + Sync;
+
+    ErasedSyncSet
+}
@@ -0,0 +1,446 @@
+use ::alloc::boxed::Box;
+use ::alloc::collections::BTreeMap;
+use ::alloc::vec::Vec;
+use ::core::any::{Any, TypeId};
+use ::core::fmt;
+use ::core::mem;
+
+/// The kind of mutation that fires a hook.
+///
+/// This `enum` is passed to the `on` and `on_type` methods on the erased hooked sets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HookKind {
+    /// A value of a type absent from the set was inserted.
+    Insert,
+    /// A value replaced the previous value of its type.
+    Replace,
+    /// A value was removed, by `remove` or `clear`.
+    Remove,
+    /// The set holding the value was dropped.
+    Drop,
+}
+
+/// A mutation of an erased hooked set, passed to its hooks.
+#[derive(Clone, Copy, Debug)]
+pub struct HookEvent<'a> {
+    /// The kind of the mutation.
+    pub kind: HookKind,
+    /// The type of the values.
+    pub type_id: TypeId,
+    /// The name of the type of the values.
+    pub type_name: &'static str,
+    /// The value that left the set, if any.
+    pub old: Option<&'a dyn Any>,
+    /// The value that entered the set, if any.
+    pub new: Option<&'a dyn Any>,
+}
+
+macro_rules! impl_erased_hooked_set {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $(+ $bound:ident)*;
+
+        $inner:ident
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            set: crate::$inner,
+            // Kept in release mode too, unlike the names of the inner set, to pass them to hooks.
+            type_names: BTreeMap<TypeId, &'static str>,
+            hooks: Vec<(HookKind, Box<dyn Fn(&HookEvent<'_>) $(+ $bound)*>)>,
+            type_hooks: BTreeMap<TypeId, Vec<(HookKind, Box<dyn Fn(&HookEvent<'_>) $(+ $bound)*>)>>,
+        }
+
+        impl $name {
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`] without hooks.")]
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// ```
+            #[must_use]
+            pub fn new() -> Self {
+                Self {
+                    set: crate::$inner::new(),
+                    type_names: BTreeMap::new(),
+                    hooks: Vec::new(),
+                    type_hooks: BTreeMap::new(),
+                }
+            }
+
+            /// Registers a hook fired by the mutations of `kind` to any type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use std::sync::{Arc, Mutex};
+            /// use erased_set::HookKind;
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            /// let log = Arc::new(Mutex::new(Vec::new()));
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.on(HookKind::Insert, {
+            ///     let log = Arc::clone(&log);
+            ///     move |event| log.lock().unwrap().push(event.type_name)
+            /// });
+            ///
+            /// set.insert(1_u8);
+            /// set.insert(2_u8);
+            /// assert_eq!(*log.lock().unwrap(), ["u8"]);
+            /// ```
+            pub fn on<F>(&mut self, kind: HookKind, f: F)
+            where
+                F: Fn(&HookEvent<'_>) $(+ $bound)* + 'static,
+            {
+                self.hooks.push((kind, Box::new(f)));
+            }
+
+            /// Registers a hook fired by the mutations of `kind` to `T`.
+            ///
+            /// The hooks of a type run before the hooks of any type, each in registration
+            /// order.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use erased_set::HookKind;
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            /// # #[derive(Debug, PartialEq)]
+            /// struct DbConfig(&'static str);
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.on_type::<DbConfig, _>(HookKind::Replace, |event| {
+            ///     let old = event.old.and_then(|old| old.downcast_ref::<DbConfig>());
+            ///     assert_eq!(old, Some(&DbConfig("postgres://old")));
+            /// });
+            ///
+            /// set.insert(DbConfig("postgres://old"));
+            /// set.insert(DbConfig("postgres://new"));
+            /// ```
+            pub fn on_type<T, F>(&mut self, kind: HookKind, f: F)
+            where
+                T: Any,
+                F: Fn(&HookEvent<'_>) $(+ $bound)* + 'static,
+            {
+                self.type_hooks
+                    .entry(TypeId::of::<T>())
+                    .or_default()
+                    .push((kind, Box::new(f)));
+            }
+
+            /// Returns `true` if the set contains no instances of any type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert!(set.is_empty());
+            /// ```
+            #[must_use]
+            pub fn is_empty(&self) -> bool {
+                self.set.is_empty()
+            }
+
+            /// Returns the number of types in the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.insert("a");
+            /// assert_eq!(set.len(), 2);
+            /// ```
+            #[must_use]
+            pub fn len(&self) -> usize {
+                self.set.len()
+            }
+
+            /// Clears the set, firing the [`Remove`](HookKind::Remove) hooks of each value.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.clear();
+            /// assert!(set.is_empty());
+            /// ```
+            pub fn clear(&mut self) {
+                self.fire_all(HookKind::Remove);
+                self.set.clear();
+                self.type_names.clear();
+            }
+
+            /// Returns `true` if the set contains an instance of `T`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u8>());
+            /// ```
+            #[must_use]
+            pub fn contains<T>(&self) -> bool
+            where
+                T: Any,
+            {
+                self.set.contains::<T>()
+            }
+
+            /// Returns a reference to an instance of `T`.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// assert_eq!(set.get::<u8>(), None);
+            /// ```
+            #[must_use]
+            pub fn get<T>(&self) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.set.get::<T>()
+            }
+
+            /// Returns a mutable reference to an instance of `T`.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned. Mutations
+            /// through the reference do not fire hooks.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// *set.get_mut::<u8>().unwrap() += 1;
+            /// assert_eq!(set.get::<u8>(), Some(&2));
+            /// ```
+            #[must_use]
+            pub fn get_mut<T>(&mut self) -> Option<&mut T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.set.get_mut::<T>()
+            }
+
+            /// Insert an instance of type `T` into the set, firing the
+            /// [`Insert`](HookKind::Insert) or [`Replace`](HookKind::Replace) hooks.
+            ///
+            /// The hooks run once the value is stored. Returns the replaced value, if any.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.insert("a"), None);
+            /// assert_eq!(set.insert("b"), Some("a"));
+            /// ```
+            pub fn insert<T>(&mut self, value: T) -> Option<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let type_id = TypeId::of::<T>();
+                let type_name = ::core::any::type_name::<T>();
+
+                self.type_names.insert(type_id, type_name);
+                let old = self.set.insert(value);
+
+                self.fire(&HookEvent {
+                    kind: if old.is_some() {
+                        HookKind::Replace
+                    } else {
+                        HookKind::Insert
+                    },
+                    type_id,
+                    type_name,
+                    old: old.as_ref().map(|old| old as &dyn Any),
+                    new: self.set.get::<T>().map(|new| new as &dyn Any),
+                });
+
+                old
+            }
+
+            /// Remove and return an instance of type `T` from the set, firing the
+            /// [`Remove`](HookKind::Remove) hooks.
+            ///
+            /// If the set did not have this type present, [`None`] is returned and no hook
+            /// fires.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.remove::<&str>(), Some("a"));
+            /// assert_eq!(set.remove::<&str>(), None);
+            /// ```
+            pub fn remove<T>(&mut self) -> Option<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let old = self.set.remove::<T>()?;
+                let type_id = TypeId::of::<T>();
+
+                self.type_names.remove(&type_id);
+                self.fire(&HookEvent {
+                    kind: HookKind::Remove,
+                    type_id,
+                    type_name: ::core::any::type_name::<T>(),
+                    old: Some(&old),
+                    new: None,
+                });
+
+                Some(old)
+            }
+
+            /// Gets an iterator over the [`TypeId`]s of stored elements, in arbitrary order.
+            pub fn type_ids(&self) -> impl Iterator<Item = &TypeId> {
+                self.set.type_ids()
+            }
+
+            #[doc = concat!("Returns the underlying [`", stringify!($inner), "`].")]
+            ///
+            /// It gives read access to the methods that this type does not forward, mutable
+            /// access is not given since it would bypass the hooks.
+            #[must_use]
+            pub fn as_set(&self) -> &crate::$inner {
+                &self.set
+            }
+
+            #[doc = concat!("Returns the underlying [`", stringify!($inner), "`], dropping the hooks without firing them.")]
+            #[must_use]
+            pub fn into_set(mut self) -> crate::$inner {
+                mem::take(&mut self.set)
+            }
+
+            fn fire(&self, event: &HookEvent<'_>) {
+                let type_hooks = self.type_hooks.get(&event.type_id).into_iter().flatten();
+
+                for (kind, hook) in type_hooks.chain(&self.hooks) {
+                    if *kind == event.kind {
+                        hook(event);
+                    }
+                }
+            }
+
+            fn fire_all(&self, kind: HookKind) {
+                if self.hooks.is_empty() && self.type_hooks.is_empty() {
+                    return;
+                }
+
+                for (type_id, old) in self.set.iter() {
+                    self.fire(&HookEvent {
+                        kind,
+                        type_id,
+                        type_name: self.type_names[&type_id],
+                        old: Some(old),
+                        new: None,
+                    });
+                }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        /// Fires the [`Drop`](HookKind::Drop) hooks of each value.
+        impl Drop for $name {
+            fn drop(&mut self) {
+                self.fire_all(HookKind::Drop);
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    .field("set", &self.set)
+                    .finish_non_exhaustive()
+            }
+        }
+    };
+}
+
+impl_erased_hooked_set! {
+    /// An [`ErasedSet`](crate::ErasedSet) firing callbacks when values enter or leave it, e.g. to
+    /// audit a shared context or to invalidate caches.
+    ///
+    /// Hooks are registered for a [`HookKind`], for all types with [`on`](Self::on) or for a
+    /// single type with [`on_type`](Self::on_type), and receive a [`HookEvent`] describing the
+    /// mutation.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use erased_set::{ErasedHookedSet, HookKind};
+    ///
+    /// struct DbConfig(&'static str);
+    ///
+    /// let cache_valid = Rc::new(Cell::new(true));
+    ///
+    /// let mut set = ErasedHookedSet::new();
+    /// set.on_type::<DbConfig, _>(HookKind::Replace, {
+    ///     let cache_valid = Rc::clone(&cache_valid);
+    ///     move |_| cache_valid.set(false)
+    /// });
+    ///
+    /// set.insert(DbConfig("postgres://localhost"));
+    /// assert!(cache_valid.get());
+    ///
+    /// set.insert(DbConfig("postgres://replica"));
+    /// assert!(!cache_valid.get());
+    /// ```
+    pub struct ErasedHookedSet: Any;
+
+    ErasedSet
+}
+
+#[cfg(feature = "send")]
+impl_erased_hooked_set! {
+    /// Like [`ErasedHookedSet`] but with a [`Send`] bound, on the values and on the hooks.
+    pub struct ErasedSendHookedSet: Any + Send;
+
+    ErasedSendSet
+}
+
+#[cfg(feature = "sync")]
+impl_erased_hooked_set! {
+    /// Like [`ErasedHookedSet`] but with a [`Send`] + [`Sync`] bound, on the values and on the
+    /// hooks.
+    pub struct ErasedSyncHookedSet: Any + Send + Sync;
+
+    ErasedSyncSet
+}
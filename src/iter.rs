@@ -0,0 +1,269 @@
+use crate::storage::{BTreeStorage, Storage};
+use crate::SmallBox;
+use ::alloc::boxed::Box;
+use ::alloc::collections::BTreeMap;
+use ::alloc::vec::{self, Vec};
+use ::core::any::{Any, TypeId};
+use ::core::iter::FusedIterator;
+
+/// An iterator over the values of an erased set.
+///
+/// This `struct` is created by the `iter` method on the erased sets.
+pub struct Iter<'a, S: Storage + 'a = BTreeStorage<SmallBox<dyn Any>>> {
+    inner: S::Iter<'a>,
+}
+
+impl<'a, S: Storage + 'a> Iter<'a, S> {
+    #[doc(hidden)]
+    pub fn new(inner: S::Iter<'a>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, B, S> Iterator for Iter<'a, S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>> + 'a,
+{
+    type Item = (TypeId, &'a B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(&type_id, boxed)| (type_id, boxed.as_ref()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, B, S> DoubleEndedIterator for Iter<'a, S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>> + 'a,
+    S::Iter<'a>: DoubleEndedIterator,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next_back()
+            .map(|(&type_id, boxed)| (type_id, boxed.as_ref()))
+    }
+}
+
+impl<'a, B, S> ExactSizeIterator for Iter<'a, S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>> + 'a,
+    S::Iter<'a>: ExactSizeIterator,
+{
+}
+
+impl<'a, B, S> FusedIterator for Iter<'a, S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>> + 'a,
+    S::Iter<'a>: FusedIterator,
+{
+}
+
+impl<'a, S> Clone for Iter<'a, S>
+where
+    S: Storage + 'a,
+    S::Iter<'a>: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// A mutable iterator over the values of an erased set.
+///
+/// This `struct` is created by the `iter_mut` method on the erased sets.
+pub struct IterMut<'a, S: Storage + 'a = BTreeStorage<SmallBox<dyn Any>>> {
+    inner: S::IterMut<'a>,
+}
+
+impl<'a, S: Storage + 'a> IterMut<'a, S> {
+    #[doc(hidden)]
+    pub fn new(inner: S::IterMut<'a>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, B, S> Iterator for IterMut<'a, S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>> + 'a,
+{
+    type Item = (TypeId, &'a mut B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(&type_id, boxed)| (type_id, boxed.as_mut()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, B, S> DoubleEndedIterator for IterMut<'a, S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>> + 'a,
+    S::IterMut<'a>: DoubleEndedIterator,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next_back()
+            .map(|(&type_id, boxed)| (type_id, boxed.as_mut()))
+    }
+}
+
+impl<'a, B, S> ExactSizeIterator for IterMut<'a, S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>> + 'a,
+    S::IterMut<'a>: ExactSizeIterator,
+{
+}
+
+impl<'a, B, S> FusedIterator for IterMut<'a, S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>> + 'a,
+    S::IterMut<'a>: FusedIterator,
+{
+}
+
+/// An owning iterator over the values of an erased set.
+///
+/// This `struct` is created by the `into_iter` method on the erased sets (provided by the
+/// [`IntoIterator`] trait).
+pub struct IntoIter<S: Storage = BTreeStorage<SmallBox<dyn Any>>> {
+    inner: S::IntoIter,
+}
+
+impl<S: Storage> IntoIter<S> {
+    #[doc(hidden)]
+    pub fn new(inner: S::IntoIter) -> Self {
+        Self { inner }
+    }
+}
+
+impl<B, S> Iterator for IntoIter<S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>>,
+{
+    type Item = Box<B>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, boxed)| boxed.into_box())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<B, S> DoubleEndedIterator for IntoIter<S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>>,
+    S::IntoIter: DoubleEndedIterator,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, boxed)| boxed.into_box())
+    }
+}
+
+impl<B, S> ExactSizeIterator for IntoIter<S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>>,
+    S::IntoIter: ExactSizeIterator,
+{
+}
+
+impl<B, S> FusedIterator for IntoIter<S>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>>,
+    S::IntoIter: FusedIterator,
+{
+}
+
+/// An iterator which uses a closure to determine if a value should be removed from an erased
+/// set.
+///
+/// This `struct` is created by the `extract_if` method on the erased sets.
+pub struct ExtractIf<'a, S, F> {
+    inner: &'a mut S,
+    debug_type_names: Option<&'a mut BTreeMap<TypeId, &'static str>>,
+    type_ids: vec::IntoIter<TypeId>,
+    pred: F,
+}
+
+impl<'a, S: Storage, F> ExtractIf<'a, S, F> {
+    #[doc(hidden)]
+    pub fn new(
+        inner: &'a mut S,
+        debug_type_names: Option<&'a mut BTreeMap<TypeId, &'static str>>,
+        pred: F,
+    ) -> Self {
+        // The storage cannot be walked while values are removed from it, so the visit order is
+        // fixed upfront.
+        let type_ids: Vec<TypeId> = inner.iter().map(|(&type_id, _)| type_id).collect();
+
+        Self {
+            inner,
+            debug_type_names,
+            type_ids: type_ids.into_iter(),
+            pred,
+        }
+    }
+}
+
+impl<B, S, F> Iterator for ExtractIf<'_, S, F>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>>,
+    F: FnMut(TypeId, &mut B) -> bool,
+{
+    type Item = Box<B>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for type_id in self.type_ids.by_ref() {
+            let Some(boxed) = self.inner.get_mut(&type_id) else {
+                continue;
+            };
+
+            if (self.pred)(type_id, boxed.as_mut()) {
+                if let Some(debug_type_names) = &mut self.debug_type_names {
+                    debug_type_names.remove(&type_id);
+                }
+
+                return self.inner.remove(&type_id).map(SmallBox::into_box);
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.type_ids.len()))
+    }
+}
+
+impl<B, S, F> FusedIterator for ExtractIf<'_, S, F>
+where
+    B: ?Sized + 'static,
+    S: Storage<Value = SmallBox<B>>,
+    F: FnMut(TypeId, &mut B) -> bool,
+{
+}
@@ -0,0 +1,585 @@
+use ::alloc::boxed::Box;
+use ::alloc::vec::{self, Vec};
+use ::core::any::{Any, TypeId};
+use ::core::fmt;
+
+use crate::storage::Storage;
+use crate::SmallBox;
+
+/// A mutation recorded by an erased journaled set.
+pub enum Mutation<B: ?Sized = dyn Any> {
+    /// A value of a type absent from the set was inserted.
+    Insert {
+        /// The type of the value.
+        type_id: TypeId,
+        /// The name of the type of the value.
+        type_name: &'static str,
+        /// A clone of the value, if it was inserted with `insert_cloned`.
+        value: Option<Box<B>>,
+    },
+    /// A value replaced the previous value of its type.
+    Replace {
+        /// The type of the value.
+        type_id: TypeId,
+        /// The name of the type of the value.
+        type_name: &'static str,
+        /// A clone of the new value, if it was inserted with `insert_cloned`.
+        value: Option<Box<B>>,
+    },
+    /// The value of a type was removed.
+    Remove {
+        /// The type of the value.
+        type_id: TypeId,
+        /// The name of the type of the value.
+        type_name: &'static str,
+    },
+    /// The set was cleared.
+    Clear,
+}
+
+/// Shows whether values were recorded rather than the values themselves.
+impl<B: ?Sized> fmt::Debug for Mutation<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Insert {
+                type_name, value, ..
+            } => f
+                .debug_struct("Insert")
+                .field("type_name", type_name)
+                .field("recorded", &value.is_some())
+                .finish(),
+            Self::Replace {
+                type_name, value, ..
+            } => f
+                .debug_struct("Replace")
+                .field("type_name", type_name)
+                .field("recorded", &value.is_some())
+                .finish(),
+            Self::Remove { type_name, .. } => f
+                .debug_struct("Remove")
+                .field("type_name", type_name)
+                .finish(),
+            Self::Clear => f.write_str("Clear"),
+        }
+    }
+}
+
+/// An entry of the journal of an erased journaled set.
+#[derive(Debug)]
+pub struct JournalEntry<B: ?Sized = dyn Any> {
+    /// The position of the mutation in the history of the set, starting at `0`.
+    pub seq: u64,
+    /// The mutation.
+    pub mutation: Mutation<B>,
+}
+
+/// The error returned when an insertion of a journal cannot be replayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The insertion was recorded without its value.
+    MissingValue {
+        /// The sequence number of the insertion.
+        seq: u64,
+        /// The name of the type of the value.
+        type_name: &'static str,
+    },
+    /// The value of the insertion is not of its recorded type.
+    TypeMismatch {
+        /// The sequence number of the insertion.
+        seq: u64,
+        /// The name of the recorded type.
+        type_name: &'static str,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingValue { seq, type_name } => {
+                write!(
+                    f,
+                    "the value of `{type_name}` inserted at {seq} was not recorded"
+                )
+            }
+            Self::TypeMismatch { seq, type_name } => {
+                write!(f, "the value inserted at {seq} is not a `{type_name}`")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for ReplayError {}
+
+macro_rules! impl_erased_journaled_set {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $(+ $bound:ident)*;
+
+        $inner:ident
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            set: crate::$inner,
+            journal: Vec<JournalEntry<dyn Any $(+ $bound)*>>,
+            seq: u64,
+        }
+
+        impl $name {
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`] with an empty journal.")]
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// ```
+            #[must_use]
+            pub fn new() -> Self {
+                Self {
+                    set: crate::$inner::new(),
+                    journal: Vec::new(),
+                    seq: 0,
+                }
+            }
+
+            /// Returns `true` if the set contains no instances of any type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert!(set.is_empty());
+            /// ```
+            #[must_use]
+            pub fn is_empty(&self) -> bool {
+                self.set.is_empty()
+            }
+
+            /// Returns the number of types in the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.insert("a");
+            /// assert_eq!(set.len(), 2);
+            /// ```
+            #[must_use]
+            pub fn len(&self) -> usize {
+                self.set.len()
+            }
+
+            /// Clears the set, recording a [`Clear`](Mutation::Clear) mutation.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.clear();
+            /// assert!(set.is_empty());
+            /// assert_eq!(set.journal().len(), 2);
+            /// ```
+            pub fn clear(&mut self) {
+                self.set.clear();
+                self.record(Mutation::Clear);
+            }
+
+            /// Returns `true` if the set contains an instance of `T`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u8>());
+            /// ```
+            #[must_use]
+            pub fn contains<T>(&self) -> bool
+            where
+                T: Any,
+            {
+                self.set.contains::<T>()
+            }
+
+            /// Returns a reference to an instance of `T`.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// assert_eq!(set.get::<u8>(), None);
+            /// ```
+            #[must_use]
+            pub fn get<T>(&self) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.set.get::<T>()
+            }
+
+            /// Insert an instance of type `T` into the set, recording the mutation without the
+            /// value.
+            ///
+            /// The journal then cannot be replayed, see [`insert_cloned`](Self::insert_cloned).
+            /// Returns the replaced value, if any.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.insert("a"), None);
+            /// assert_eq!(set.insert("b"), Some("a"));
+            /// ```
+            pub fn insert<T>(&mut self, value: T) -> Option<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.insert_recorded(value, None)
+            }
+
+            /// Insert an instance of type `T` into the set, recording the mutation with a clone
+            /// of the value.
+            ///
+            /// Returns the replaced value, if any.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use erased_set::Mutation;
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert_cloned(String::from("a"));
+            ///
+            /// let entry = &set.journal()[0];
+            /// let Mutation::Insert { value: Some(value), .. } = &entry.mutation else {
+            ///     unreachable!()
+            /// };
+            /// assert_eq!(value.downcast_ref::<String>().map(String::as_str), Some("a"));
+            /// ```
+            pub fn insert_cloned<T>(&mut self, value: T) -> Option<T>
+            where
+                T: Any + Clone $(+ $bound)*,
+            {
+                let recorded = Box::new(value.clone());
+                self.insert_recorded(value, Some(recorded))
+            }
+
+            /// Remove and return an instance of type `T` from the set, recording a
+            /// [`Remove`](Mutation::Remove) mutation.
+            ///
+            /// If the set did not have this type present, [`None`] is returned and nothing is
+            /// recorded.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.remove::<&str>(), Some("a"));
+            /// assert_eq!(set.remove::<&str>(), None);
+            /// assert_eq!(set.journal().len(), 2);
+            /// ```
+            pub fn remove<T>(&mut self) -> Option<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let value = self.set.remove::<T>()?;
+
+                self.record(Mutation::Remove {
+                    type_id: TypeId::of::<T>(),
+                    type_name: ::core::any::type_name::<T>(),
+                });
+
+                Some(value)
+            }
+
+            /// Gets an iterator over the [`TypeId`]s of stored elements, in arbitrary order.
+            pub fn type_ids(&self) -> impl Iterator<Item = &TypeId> {
+                self.set.type_ids()
+            }
+
+            /// Returns the mutations recorded since the last call to
+            /// [`drain_journal`](Self::drain_journal).
+            #[must_use]
+            pub fn journal(&self) -> &[JournalEntry<dyn Any $(+ $bound)*>] {
+                &self.journal
+            }
+
+            /// Removes the recorded mutations from the journal and returns them, e.g. to ship
+            /// them to a replica with [`replay_into`](Self::replay_into).
+            ///
+            /// The sequence numbers keep increasing across calls.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            #[doc = concat!("let mut replica = ", stringify!($name), "::replay([]).unwrap();")]
+            ///
+            /// set.insert_cloned(1_u8);
+            #[doc = concat!(stringify!($name), "::replay_into(&mut replica, set.drain_journal()).unwrap();")]
+            ///
+            /// set.insert_cloned(2_u8);
+            /// let changes: Vec<_> = set.drain_journal().collect();
+            /// assert_eq!(changes[0].seq, 1);
+            #[doc = concat!(stringify!($name), "::replay_into(&mut replica, changes).unwrap();")]
+            ///
+            /// assert_eq!(replica.get::<u8>(), Some(&2));
+            /// ```
+            pub fn drain_journal(&mut self) -> vec::Drain<'_, JournalEntry<dyn Any $(+ $bound)*>> {
+                self.journal.drain(..)
+            }
+
+            #[doc = concat!("Reconstructs a [`", stringify!($inner), "`] by applying the mutations of `journal` to an empty set.")]
+            ///
+            /// # Errors
+            ///
+            /// Returns an error if an insertion was recorded without its value, or with a value of
+            /// another type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert_cloned("a");
+            /// set.insert_cloned(1_u8);
+            /// set.remove::<&str>();
+            ///
+            #[doc = concat!("let replica = ", stringify!($name), "::replay(set.drain_journal()).unwrap();")]
+            /// assert_eq!(replica.get::<u8>(), Some(&1));
+            /// assert!(!replica.contains::<&str>());
+            ///
+            /// set.insert(2_u16);
+            #[doc = concat!("assert!(", stringify!($name), "::replay(set.drain_journal()).is_err());")]
+            /// ```
+            pub fn replay<I>(journal: I) -> Result<crate::$inner, ReplayError>
+            where
+                I: IntoIterator<Item = JournalEntry<dyn Any $(+ $bound)*>>,
+            {
+                let mut set = crate::$inner::new();
+                Self::replay_into(&mut set, journal)?;
+                Ok(set)
+            }
+
+            /// Applies the mutations of `journal` to `set`, in order.
+            ///
+            /// # Errors
+            ///
+            /// Returns an error if an insertion was recorded without its value, or with a value of
+            /// another type, the mutations before it are applied.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use erased_set::{JournalEntry, Mutation, ReplayError};
+            #[doc = concat!("use erased_set::{", stringify!($name), ", ", stringify!($inner), "};")]
+            /// use std::any::TypeId;
+            ///
+            /// let forged = JournalEntry {
+            ///     seq: 0,
+            ///     mutation: Mutation::Insert {
+            ///         type_id: TypeId::of::<[usize; 8]>(),
+            ///         type_name: "[usize; 8]",
+            ///         value: Some(Box::new(1_u8) as _),
+            ///     },
+            /// };
+            ///
+            #[doc = concat!("let mut set = ", stringify!($inner), "::new();")]
+            #[doc = concat!("let result = ", stringify!($name), "::replay_into(&mut set, [forged]);")]
+            /// assert_eq!(
+            ///     result,
+            ///     Err(ReplayError::TypeMismatch { seq: 0, type_name: "[usize; 8]" })
+            /// );
+            /// assert!(set.is_empty());
+            /// ```
+            pub fn replay_into<I>(set: &mut crate::$inner, journal: I) -> Result<(), ReplayError>
+            where
+                I: IntoIterator<Item = JournalEntry<dyn Any $(+ $bound)*>>,
+            {
+                for JournalEntry { seq, mutation } in journal {
+                    match mutation {
+                        Mutation::Insert { type_id, type_name, value }
+                        | Mutation::Replace { type_id, type_name, value } => {
+                            let value = value.ok_or(ReplayError::MissingValue { seq, type_name })?;
+
+                            // The fields of an entry are public, they may not match.
+                            if Any::type_id(value.as_ref()) != type_id {
+                                return Err(ReplayError::TypeMismatch { seq, type_name });
+                            }
+
+                            // Stored under the recorded name, rather than the name of the box.
+                            #[cfg(debug_assertions)]
+                            set.debug_type_names.insert(type_id, type_name);
+
+                            // The value has the recorded type, checked above.
+                            let storage = unsafe { set.inner.as_storage_mut() };
+                            let _ = Storage::insert(storage, type_id, SmallBox::from(value));
+                        }
+                        Mutation::Remove { type_id, .. } => {
+                            #[cfg(debug_assertions)]
+                            set.debug_type_names.remove(&type_id);
+
+                            let _ = set.inner.remove(&type_id);
+                        }
+                        Mutation::Clear => set.clear(),
+                    }
+                }
+
+                Ok(())
+            }
+
+            #[doc = concat!("Returns the underlying [`", stringify!($inner), "`].")]
+            ///
+            /// It gives read access to the methods that this type does not forward, mutable
+            /// access is not given since it would bypass the journal.
+            #[must_use]
+            pub fn as_set(&self) -> &crate::$inner {
+                &self.set
+            }
+
+            #[doc = concat!("Returns the underlying [`", stringify!($inner), "`], dropping the journal.")]
+            #[must_use]
+            pub fn into_set(self) -> crate::$inner {
+                self.set
+            }
+
+            fn insert_recorded<T>(
+                &mut self,
+                value: T,
+                recorded: Option<Box<dyn Any $(+ $bound)*>>,
+            ) -> Option<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let type_id = TypeId::of::<T>();
+                let type_name = ::core::any::type_name::<T>();
+                let old = self.set.insert(value);
+
+                self.record(if old.is_some() {
+                    Mutation::Replace { type_id, type_name, value: recorded }
+                } else {
+                    Mutation::Insert { type_id, type_name, value: recorded }
+                });
+
+                old
+            }
+
+            fn record(&mut self, mutation: Mutation<dyn Any $(+ $bound)*>) {
+                self.journal.push(JournalEntry { seq: self.seq, mutation });
+                self.seq += 1;
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    .field("set", &self.set)
+                    .field("journal", &self.journal)
+                    .finish()
+            }
+        }
+    };
+}
+
+impl_erased_journaled_set! {
+    /// An [`ErasedSet`](crate::ErasedSet) recording a journal of its mutations, e.g. to debug
+    /// them or to replicate the set.
+    ///
+    /// Each insertion, replacement, removal and clear is recorded as a [`JournalEntry`] with a
+    /// sequence number. Values are recorded when inserted with
+    /// [`insert_cloned`](Self::insert_cloned), the journal can then be replayed to reconstruct
+    /// the set.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedJournaledSet;
+    ///
+    /// # #[derive(Clone, Debug, PartialEq)]
+    /// struct Config(&'static str);
+    ///
+    /// let mut set = ErasedJournaledSet::new();
+    /// set.insert_cloned(Config("localhost"));
+    /// set.insert_cloned(443_u16);
+    /// set.remove::<u16>();
+    ///
+    /// let replica = ErasedJournaledSet::replay(set.drain_journal()).unwrap();
+    /// assert_eq!(replica.get::<Config>(), Some(&Config("localhost")));
+    /// assert_eq!(replica.len(), set.len());
+    /// ```
+    pub struct ErasedJournaledSet: Any;
+
+    ErasedSet
+}
+
+#[cfg(feature = "send")]
+impl_erased_journaled_set! {
+    /// Like [`ErasedJournaledSet`] but with a [`Send`] bound, the journal can be shipped to
+    /// another thread.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedSendJournaledSet;
+    ///
+    /// let mut set = ErasedSendJournaledSet::new();
+    /// let mut replica = ErasedSendJournaledSet::replay([]).unwrap();
+    ///
+    /// set.insert_cloned(String::from("a"));
+    /// let changes: Vec<_> = set.drain_journal().collect();
+    ///
+    /// let replica = std::thread::spawn(move || {
+    ///     ErasedSendJournaledSet::replay_into(&mut replica, changes).unwrap();
+    ///     replica
+    /// })
+    /// .join()
+    /// .unwrap();
+    ///
+    /// assert_eq!(replica.get::<String>().map(String::as_str), Some("a"));
+    /// ```
+    pub struct ErasedSendJournaledSet: Any + Send;
+
+    ErasedSendSet
+}
+
+#[cfg(feature = "sync")]
+impl_erased_journaled_set! {
+    /// Like [`ErasedJournaledSet`] but with a [`Send`] + [`Sync`] bound.
+    pub struct ErasedSyncJournaledSet: Any + Send + Sync;
+
+    ErasedSyncSet
+}
@@ -0,0 +1,323 @@
+use ::alloc::collections::BTreeSet;
+use ::core::any::{Any, TypeId};
+use ::core::fmt;
+
+use crate::ErasedSet;
+
+/// An [`ErasedSet`] layered over a parent, e.g. a request scope over a tenant scope over an
+/// application scope.
+///
+/// Reads search the layers from this one outward and return the first value found, writes only
+/// affect this layer. A value of this layer shadows the values of the same type of its parents.
+///
+/// ## Example
+///
+/// ```
+/// use erased_set::LayeredErasedSet;
+///
+/// # #[derive(Debug, PartialEq)]
+/// struct Database(&'static str);
+/// # #[derive(Debug, PartialEq)]
+/// struct Tenant(&'static str);
+///
+/// let mut application = LayeredErasedSet::new();
+/// application.insert(Database("postgres://localhost"));
+/// application.insert(Tenant("none"));
+///
+/// let mut request = LayeredErasedSet::with_parent(&application);
+/// request.insert(Tenant("acme"));
+///
+/// assert_eq!(request.get::<Tenant>(), Some(&Tenant("acme")));
+/// assert_eq!(request.get::<Database>(), Some(&Database("postgres://localhost")));
+/// assert_eq!(request.locate::<Database>(), Some(1));
+/// ```
+pub struct LayeredErasedSet<'p> {
+    set: ErasedSet,
+    parent: Option<&'p LayeredErasedSet<'p>>,
+}
+
+impl<'p> LayeredErasedSet<'p> {
+    /// Creates an empty [`LayeredErasedSet`] without a parent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let set = LayeredErasedSet::new();
+    /// assert!(set.parent().is_none());
+    /// ```
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            set: ErasedSet::new(),
+            parent: None,
+        }
+    }
+
+    /// Creates an empty [`LayeredErasedSet`] over `parent`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let mut parent = LayeredErasedSet::new();
+    /// parent.insert("a");
+    ///
+    /// let set = LayeredErasedSet::with_parent(&parent);
+    /// assert_eq!(set.get::<&str>(), Some(&"a"));
+    /// ```
+    #[must_use]
+    pub fn with_parent(parent: &'p LayeredErasedSet<'p>) -> Self {
+        Self {
+            set: ErasedSet::new(),
+            parent: Some(parent),
+        }
+    }
+
+    /// Returns the parent of this layer, if any.
+    #[must_use]
+    pub fn parent(&self) -> Option<&'p LayeredErasedSet<'p>> {
+        self.parent
+    }
+
+    /// Returns the values of this layer only.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let mut parent = LayeredErasedSet::new();
+    /// parent.insert("a");
+    ///
+    /// let mut set = LayeredErasedSet::with_parent(&parent);
+    /// set.insert(1_u8);
+    ///
+    /// assert_eq!(set.local().len(), 1);
+    /// ```
+    #[must_use]
+    pub fn local(&self) -> &ErasedSet {
+        &self.set
+    }
+
+    /// Returns the values of this layer only, e.g. to use the mutating methods of [`ErasedSet`]
+    /// that this type does not forward.
+    #[must_use]
+    pub fn local_mut(&mut self) -> &mut ErasedSet {
+        &mut self.set
+    }
+
+    /// Gets an iterator over the layers, from this one outward.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let parent = LayeredErasedSet::new();
+    /// let set = LayeredErasedSet::with_parent(&parent);
+    /// assert_eq!(set.layers().count(), 2);
+    /// ```
+    pub fn layers(&self) -> impl Iterator<Item = &ErasedSet> {
+        ::core::iter::successors(Some(self), |layer| layer.parent).map(|layer| &layer.set)
+    }
+
+    /// Returns `true` if any layer contains an instance of `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let mut parent = LayeredErasedSet::new();
+    /// parent.insert("a");
+    ///
+    /// let set = LayeredErasedSet::with_parent(&parent);
+    /// assert!(set.contains::<&str>());
+    /// assert!(!set.contains::<u8>());
+    /// ```
+    #[must_use]
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Any,
+    {
+        self.layers().any(ErasedSet::contains::<T>)
+    }
+
+    /// Returns the depth of the layer that answers reads of `T`, `0` being this layer.
+    ///
+    /// If no layer has an instance of `T`, [`None`] is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let mut parent = LayeredErasedSet::new();
+    /// parent.insert("a");
+    /// parent.insert(1_u8);
+    ///
+    /// let mut set = LayeredErasedSet::with_parent(&parent);
+    /// set.insert(2_u8);
+    ///
+    /// assert_eq!(set.locate::<u8>(), Some(0));
+    /// assert_eq!(set.locate::<&str>(), Some(1));
+    /// assert_eq!(set.locate::<u32>(), None);
+    /// ```
+    #[must_use]
+    pub fn locate<T>(&self) -> Option<usize>
+    where
+        T: Any,
+    {
+        self.layers().position(ErasedSet::contains::<T>)
+    }
+
+    /// Returns a reference to the instance of `T` of the innermost layer that has one.
+    ///
+    /// If no layer has an instance of `T`, [`None`] is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let mut parent = LayeredErasedSet::new();
+    /// parent.insert("a");
+    /// parent.insert(1_u8);
+    ///
+    /// let mut set = LayeredErasedSet::with_parent(&parent);
+    /// set.insert(2_u8);
+    ///
+    /// assert_eq!(set.get::<u8>(), Some(&2));
+    /// assert_eq!(set.get::<&str>(), Some(&"a"));
+    /// ```
+    #[must_use]
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: Any,
+    {
+        self.layers().find_map(ErasedSet::get::<T>)
+    }
+
+    /// Returns a mutable reference to the instance of `T` of this layer.
+    ///
+    /// The values of the parents cannot be mutated, [`None`] is returned if this layer does not
+    /// have an instance of `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let mut parent = LayeredErasedSet::new();
+    /// parent.insert(1_u8);
+    ///
+    /// let mut set = LayeredErasedSet::with_parent(&parent);
+    /// assert_eq!(set.get_mut::<u8>(), None);
+    ///
+    /// set.insert(2_u8);
+    /// *set.get_mut::<u8>().unwrap() += 1;
+    /// assert_eq!(set.get::<u8>(), Some(&3));
+    /// ```
+    #[must_use]
+    pub fn get_mut<T>(&mut self) -> Option<&mut T>
+    where
+        T: Any,
+    {
+        self.set.get_mut::<T>()
+    }
+
+    /// Insert an instance of type `T` into this layer, shadowing the instances of the parents.
+    ///
+    /// Returns the value replaced in this layer, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let mut parent = LayeredErasedSet::new();
+    /// parent.insert("a");
+    ///
+    /// let mut set = LayeredErasedSet::with_parent(&parent);
+    /// assert_eq!(set.insert("b"), None);
+    /// assert_eq!(set.insert("c"), Some("b"));
+    /// assert_eq!(parent.get::<&str>(), Some(&"a"));
+    /// ```
+    pub fn insert<T>(&mut self, value: T) -> Option<T>
+    where
+        T: Any,
+    {
+        self.set.insert(value)
+    }
+
+    /// Remove and return the instance of type `T` of this layer, uncovering the instance of the
+    /// parents, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let mut parent = LayeredErasedSet::new();
+    /// parent.insert("a");
+    ///
+    /// let mut set = LayeredErasedSet::with_parent(&parent);
+    /// set.insert("b");
+    /// assert_eq!(set.remove::<&str>(), Some("b"));
+    /// assert_eq!(set.remove::<&str>(), None);
+    /// assert_eq!(set.get::<&str>(), Some(&"a"));
+    /// ```
+    pub fn remove<T>(&mut self) -> Option<T>
+    where
+        T: Any,
+    {
+        self.set.remove::<T>()
+    }
+
+    /// Gets an iterator over the [`TypeId`]s of the elements of all the layers, without
+    /// duplicates and in arbitrary order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::LayeredErasedSet;
+    ///
+    /// let mut parent = LayeredErasedSet::new();
+    /// parent.insert("a");
+    /// parent.insert(1_u8);
+    ///
+    /// let mut set = LayeredErasedSet::with_parent(&parent);
+    /// set.insert(2_u8);
+    ///
+    /// assert_eq!(set.type_ids().count(), 2);
+    /// ```
+    pub fn type_ids(&self) -> impl Iterator<Item = &TypeId> {
+        self.layers()
+            .flat_map(ErasedSet::type_ids)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+    }
+}
+
+impl Default for LayeredErasedSet<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Creates a layer without a parent from the values of `set`.
+impl From<ErasedSet> for LayeredErasedSet<'_> {
+    fn from(set: ErasedSet) -> Self {
+        Self { set, parent: None }
+    }
+}
+
+/// Shows the layers, from this one outward.
+impl fmt::Debug for LayeredErasedSet<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.layers()).finish()
+    }
+}
@@ -37,55 +37,346 @@
 //!
 //! ## Features
 //!
-//! | name        | default ? | description               |
-//! | ----------- | --------- | ------------------------- |
-//! | `send`      | yes       | Enables [`ErasedSendSet`] |
-//! | `sync`      | yes       | Enables [`ErasedSyncSet`] |
+//! | name         | default ? | description                                                     |
+//! | ------------ | --------- | --------------------------------------------------------------- |
+//! | `send`       | yes       | Enables [`ErasedSendSet`] and the other `Send` flavors          |
+//! | `sync`       | yes       | Enables [`ErasedSyncSet`] and the other `Send` + `Sync` flavors |
+//! | `hashbrown`  | no        | Enables `HashStorage`                                           |
+//! | `serde`      | no        | Enables the `registry` module                                   |
+//! | `std`        | no        | Enables `ErasedSyncOnceSet`                                     |
+//! | `concurrent` | no        | Enables `ErasedConcurrentSet` and `std`                         |
+//!
+//! The sets wrapping an erased set, e.g. [`ErasedTrackedSet`], come in the same flavors as the
+//! erased sets, e.g. `ErasedSendTrackedSet` and `ErasedSyncTrackedSet`. The sets built on shared
+//! ownership or interior mutability, e.g. [`ErasedArcSet`] and [`ErasedOnceSet`], only have a
+//! `Send` + `Sync` flavor, which they need to cross threads.
+//!
+//! ## Custom bounds
+//!
+//! Sets with other auto trait bounds, e.g. [`UnwindSafe`](::core::panic::UnwindSafe), can be
+//! generated with the [`impl_erased_set!`] macro, which can also select a different
+//! [`storage`] backend.
+//!
+//! ## Capabilities
+//!
+//! [`ErasedCloneSet`] only accepts types that implement [`Clone`] and can itself be cloned, e.g. to
+//! fork a configuration. Sets that can be compared and hashed are generated with the
+//! [`impl_erased_set!`] macro.
+//!
+//! ## Multiple values per type
+//!
+//! [`ErasedMultiSet`] holds any number of instances of each type, e.g. to queue events.
+//!
+//! ## Layered sets
+//!
+//! [`LayeredErasedSet`] falls back to its parents when a type is not found, e.g. to model nested
+//! scopes.
+//!
+//! ## Keyed sets
+//!
+//! [`ErasedMap`] holds an erased set per key, e.g. the components of entities, in a single
+//! collection.
+//!
+//! ## Shared values
+//!
+//! [`ErasedArcSet`] stores each value behind an [`Arc`](::alloc::sync::Arc), handing out `Arc<T>`
+//! and `Weak<T>` handles, so that several sets can share a value without cloning it.
+//!
+//! ## Persistent sets
+//!
+//! [`ErasedPersistentSet`] can be cloned in O(1), e.g. to fork a context at each layer of a
+//! pipeline, its values are shared between clones and copied on write.
+//!
+//! ## Change detection
+//!
+//! [`ErasedTrackedSet`] records a [`Tick`] per type on each change, e.g. to only recompute the
+//! state derived from the types that changed since the last frame.
+//!
+//! ## Mutation hooks
+//!
+//! [`ErasedHookedSet`] runs callbacks when values are inserted, replaced, removed or dropped,
+//! e.g. to invalidate a cache when a configuration is replaced.
+//!
+//! ## Journaling
+//!
+//! [`ErasedJournaledSet`] records its mutations in a journal, which can be replayed to replicate
+//! the set, e.g. on a worker thread.
+//!
+//! ## Undo and redo
+//!
+//! [`ErasedHistorySet`] moves replaced and removed values into a history, e.g. to undo the
+//! edits of a document back to a checkpoint.
+//!
+//! ## Concurrent access
+//!
+//! `ErasedConcurrentSet` can be mutated from `&self` and locks each type separately, so that
+//! threads accessing unrelated types do not contend on a single lock.
+//!
+//! ## Lazy initialization
+//!
+//! [`ErasedOnceSet`] initializes each type at most once from `&self`, e.g. for lazily
+//! initialized services, and `ErasedSyncOnceSet` can live in a `static`. [`FrozenErasedSet`]
+//! inserts from `&self` while references to other values are held, e.g. in builders.
 //!
 //! ## `no_std` support
 //!
-//! This crate is `no_std` compatible, however it still requires `alloc`.
+//! This crate is `no_std` compatible, however it still requires `alloc`. The `std` and `concurrent`
+//! features require `std`.
 
 #![no_std]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use ::alloc::boxed::Box;
+    pub use ::alloc::collections::{BTreeMap, BTreeSet};
+    pub use ::alloc::vec::Vec;
+
+    pub use crate::transaction::{Stage, Transactional};
+
+    use crate::storage::Storage;
+
+    /// The storage of a set generated by `impl_erased_set!`.
+    ///
+    /// The fields of the set are visible in the module invoking the macro, the storage is
+    /// therefore only mutably accessible through `unsafe` methods: safe code could otherwise store
+    /// a value under the type id of another type.
+    pub struct Inner<S>(S);
+
+    impl<S: Storage> Inner<S> {
+        pub fn with_capacity(capacity: usize) -> Self {
+            Self(S::with_capacity(capacity))
+        }
+
+        pub fn as_storage(&self) -> &S {
+            &self.0
+        }
+
+        /// # Safety
+        ///
+        /// The storage must only hold values stored under their own type id.
+        pub unsafe fn as_storage_mut(&mut self) -> &mut S {
+            &mut self.0
+        }
+
+        pub fn into_storage(self) -> S {
+            self.0
+        }
+
+        pub fn reserve(&mut self, additional: usize) {
+            self.0.reserve(additional);
+        }
+
+        pub fn shrink_to_fit(&mut self) {
+            self.0.shrink_to_fit();
+        }
+
+        pub fn clear(&mut self) {
+            self.0.clear();
+        }
+
+        pub fn remove(&mut self, type_id: &::core::any::TypeId) -> Option<S::Value> {
+            self.0.remove(type_id)
+        }
+    }
+
+    impl<S: Storage> Default for Inner<S> {
+        fn default() -> Self {
+            Self(S::default())
+        }
+    }
+
+    /// A `key: value` entry of the `Debug` output of an erased set, or only the key if the value
+    /// cannot be formatted.
+    pub struct DebugEntry<K, V> {
+        pub key: K,
+        pub value: Option<V>,
+    }
+
+    impl<K: ::core::fmt::Debug, V: ::core::fmt::Debug> ::core::fmt::Debug for DebugEntry<K, V> {
+        fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+            self.key.fmt(f)?;
+
+            if let Some(value) = &self.value {
+                f.write_str(": ")?;
+                value.fmt(f)?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+mod arc;
+#[cfg(feature = "concurrent")]
+mod concurrent;
+mod entry;
+mod frozen;
+mod history;
+mod hooks;
+mod iter;
+mod journal;
+mod layered;
+mod many;
+mod map;
+mod merge;
+mod multi;
+mod once;
+mod persistent;
+#[cfg(feature = "serde")]
+pub mod registry;
+mod small_box;
+pub mod storage;
+mod tracked;
+mod transaction;
+
+pub use arc::ErasedArcSet;
+#[cfg(feature = "sync")]
+pub use arc::ErasedSyncArcSet;
+#[cfg(feature = "concurrent")]
+pub use concurrent::{ErasedConcurrentSet, ReadGuard, WriteGuard};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use frozen::FrozenErasedSet;
+pub use history::ErasedHistorySet;
+#[cfg(feature = "send")]
+pub use history::ErasedSendHistorySet;
+#[cfg(feature = "sync")]
+pub use history::ErasedSyncHistorySet;
+#[cfg(feature = "send")]
+pub use hooks::ErasedSendHookedSet;
+#[cfg(feature = "sync")]
+pub use hooks::ErasedSyncHookedSet;
+pub use hooks::{ErasedHookedSet, HookEvent, HookKind};
+pub use iter::{ExtractIf, IntoIter, Iter, IterMut};
+#[cfg(feature = "send")]
+pub use journal::ErasedSendJournaledSet;
+#[cfg(feature = "sync")]
+pub use journal::ErasedSyncJournaledSet;
+pub use journal::{ErasedJournaledSet, JournalEntry, Mutation, ReplayError};
+pub use layered::LayeredErasedSet;
+pub use many::TypeTuple;
+pub use map::ErasedMap;
+#[cfg(feature = "send")]
+pub use map::ErasedSendMap;
+#[cfg(feature = "sync")]
+pub use map::ErasedSyncMap;
+pub use merge::{MergePolicy, MergeReport};
+#[cfg(feature = "send")]
+pub use multi::ErasedSendMultiSet;
+#[cfg(feature = "sync")]
+pub use multi::ErasedSyncMultiSet;
+pub use multi::{ErasedMultiSet, MultiIter, MultiIterMut};
+pub use once::ErasedOnceSet;
+#[cfg(feature = "std")]
+pub use once::ErasedSyncOnceSet;
+#[cfg(feature = "sync")]
+pub use persistent::ErasedSyncPersistentSet;
+pub use persistent::{ErasedPersistentSet, Snapshot};
+pub use small_box::{SmallBox, Vtable};
+#[cfg(feature = "send")]
+pub use tracked::ErasedSendTrackedSet;
+#[cfg(feature = "sync")]
+pub use tracked::ErasedSyncTrackedSet;
+pub use tracked::{ErasedTrackedSet, Tick, TrackedEntry, TrackedOccupiedEntry, TrackedVacantEntry};
+pub use transaction::Transaction;
 
 /// Implement an erased set with the specified bounds.
 ///
+/// The generated set has the same API as [`ErasedSet`] and implements
+/// [`Debug`](::core::fmt::Debug). Trait objects for multiple traits are not currently supported
+/// (see <https://github.com/rust-lang/rfcs/issues/2035> for more details), so the bounds must be
+/// auto traits, e.g. [`Send`], [`Sync`], [`UnwindSafe`](::core::panic::UnwindSafe) or
+/// [`RefUnwindSafe`](::core::panic::RefUnwindSafe).
+///
+/// The bounds may also include [`Clone`], [`PartialEq`], [`Eq`] and [`Hash`](::core::hash::Hash):
+/// the set then only accepts types that implement these traits, captures their implementation
+/// at insertion and implements the same traits itself, see [`ErasedCloneSet`]. Such a set cannot
+/// be built from boxed values.
+///
+/// The values are stored in a [`BTreeStorage`](storage::BTreeStorage) unless another backend is
+/// selected with `in`, see the [`storage`] module.
+///
 /// # Syntax
 ///
 /// ```rust,ignore
 /// impl_erased_set! {
-///     [pub] struct NAME: Any [+ BOUNDS ...];
+///     [pub] struct NAME: Any [+ BOUNDS ...] [in STORAGE];
 /// }
 /// ```
 ///
 /// # Example
 ///
-/// ```rust,ignore
+/// ```
+/// use std::panic::{self, RefUnwindSafe, UnwindSafe};
+///
+/// erased_set::impl_erased_set! {
+///     /// A set of erased types that can cross a `catch_unwind` boundary.
+///     #[derive(Default)]
+///     pub struct ErasedUnwindSafeSet: Any + UnwindSafe + RefUnwindSafe;
+/// }
+///
+/// let mut set = ErasedUnwindSafeSet::new();
+/// set.insert(1_u32);
+///
+/// let result = panic::catch_unwind(|| set.get::<u32>().copied());
+/// assert_eq!(result.ok(), Some(Some(1)));
+/// ```
+///
+/// A set that can be compared and deduplicated:
+///
+/// ```
+/// use std::collections::HashSet;
+///
+/// erased_set::impl_erased_set! {
+///     #[derive(Default)]
+///     pub struct ErasedEqSet: Any + PartialEq + Eq + Hash;
+/// }
+///
+/// let mut a = ErasedEqSet::new();
+/// a.insert("a");
+/// a.insert(1_u32);
+///
+/// let mut b = ErasedEqSet::new();
+/// b.insert(1_u32);
+/// b.insert("a");
+/// assert_eq!(a, b);
+///
+/// let sets: HashSet<_> = [a, b].into_iter().collect();
+/// assert_eq!(sets.len(), 1);
+/// ```
+///
+/// The storage of the generated set cannot be modified from safe code, even in the module
+/// invoking the macro, so that a value cannot be stored under the type id of another type:
+///
+/// ```compile_fail
+/// use erased_set::storage::Storage;
+/// use erased_set::SmallBox;
+/// use std::any::{Any, TypeId};
+///
 /// erased_set::impl_erased_set! {
-///     /// A set of erased types.
-///     #[derive(Debug, Default)]
-///     pub struct ErasedSet: Any;
+///     struct ErasedAnySet: Any;
 /// }
+///
+/// let mut set = ErasedAnySet::new();
+/// let value: Box<dyn Any> = Box::new(String::new());
+/// Storage::insert(&mut set.inner, TypeId::of::<[usize; 8]>(), SmallBox::from(value));
 /// ```
-// This macro is not currently public because trait objects for multiple traits are not currently
-// supported, see <https://github.com/rust-lang/rfcs/issues/2035> for more details.
+#[macro_export]
 macro_rules! impl_erased_set {
     (
-        $(#[$attr:meta])*
-        $vis:vis struct $name:ident: Any $(+ $bounds:tt)*;
+        @impl $fence:literal [$storage:ty] [$($bound:tt)*] [$($cap:ident ($($cap_bound:tt)*))*]
+        [$(#[$attr:meta])* $vis:vis struct $name:ident]
     ) => {
         $(#[$attr])*
         $vis struct $name {
             #[doc(hidden)]
-            inner: ::alloc::collections::BTreeMap<
-                ::core::any::TypeId,
-                ::alloc::boxed::Box<dyn ::core::any::Any $(+ $bounds)*>,
-            >,
+            inner: $crate::__private::Inner<$storage>,
             #[doc(hidden)]
             #[cfg(debug_assertions)]
-            debug_type_names: ::alloc::collections::BTreeMap<
+            debug_type_names: $crate::__private::BTreeMap<
                 ::core::any::TypeId,
                 &'static str
             >,
@@ -99,7 +390,7 @@ macro_rules! impl_erased_set {
             ///
             /// # Examples
             ///
-            /// ```
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let set = ", stringify!($name), "::new();")]
@@ -107,17 +398,72 @@ macro_rules! impl_erased_set {
             #[must_use]
             pub fn new() -> Self {
                 Self {
-                    inner: ::alloc::collections::BTreeMap::new(),
+                    inner: ::core::default::Default::default(),
                     #[cfg(debug_assertions)]
-                    debug_type_names: ::alloc::collections::BTreeMap::new(),
+                    debug_type_names: $crate::__private::BTreeMap::new(),
                 }
             }
 
-            /// Returns `true` if the set contains no instances of any type.
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`] with space for at least `capacity` types.")]
+            ///
+            /// The capacity is ignored if the storage backend does not support pre-allocation,
+            /// see the [`storage`](crate::storage) module.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::with_capacity(10);")]
+            /// assert!(set.is_empty());
+            /// ```
+            #[must_use]
+            pub fn with_capacity(capacity: usize) -> Self {
+                Self {
+                    inner: $crate::__private::Inner::with_capacity(capacity),
+                    #[cfg(debug_assertions)]
+                    debug_type_names: $crate::__private::BTreeMap::new(),
+                }
+            }
+
+            /// Reserves capacity for at least `additional` more types.
+            ///
+            /// Does nothing if the storage backend does not support pre-allocation.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.reserve(10);
+            /// ```
+            pub fn reserve(&mut self, additional: usize) {
+                self.inner.reserve(additional);
+            }
+
+            /// Shrinks the capacity of the set as much as possible.
+            ///
+            /// Does nothing if the storage backend does not support pre-allocation.
             ///
             /// # Examples
             ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::with_capacity(10);")]
+            /// set.insert("a");
+            /// set.shrink_to_fit();
             /// ```
+            pub fn shrink_to_fit(&mut self) {
+                self.inner.shrink_to_fit();
+            }
+
+            /// Returns `true` if the set contains no instances of any type.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let set = ", stringify!($name), "::new();")]
@@ -125,14 +471,14 @@ macro_rules! impl_erased_set {
             /// ```
             #[must_use]
             pub fn is_empty(&self) -> bool {
-                self.inner.is_empty()
+                $crate::storage::Storage::is_empty(self.inner.as_storage())
             }
 
             /// Returns the number of types in the set.
             ///
             /// # Examples
             ///
-            /// ```
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
@@ -142,14 +488,14 @@ macro_rules! impl_erased_set {
             /// ```
             #[must_use]
             pub fn len(&self) -> usize {
-                self.inner.len()
+                $crate::storage::Storage::len(self.inner.as_storage())
             }
 
             /// Clears the set. Keep allocated memory for reuse.
             ///
             /// # Examples
             ///
-            /// ```
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
@@ -159,13 +505,119 @@ macro_rules! impl_erased_set {
             /// ```
             pub fn clear(&mut self) {
                 self.inner.clear();
+
+                #[cfg(debug_assertions)]
+                self.debug_type_names.clear();
             }
 
-            /// Returns `true` if the set contains an instance of `T`.
+            /// Clears the set, returning all values as an iterator.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// set.insert(1_u32);
+            /// assert_eq!(set.drain().count(), 2);
+            /// assert!(set.is_empty());
+            /// ```
+            pub fn drain(&mut self) -> $crate::IntoIter<$storage> {
+                #[cfg(debug_assertions)]
+                self.debug_type_names.clear();
+
+                $crate::IntoIter::new($crate::storage::Storage::into_iter(
+                    ::core::mem::take(&mut self.inner).into_storage(),
+                ))
+            }
+
+            /// Retains only the values specified by the predicate.
+            ///
+            /// In other words, remove all values `v` for which `f(type_id, &mut v)` returns
+            /// `false`.
             ///
             /// # Examples
             ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// set.insert(1_u32);
+            /// set.retain(|_, value| !value.is::<u32>());
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u32>());
+            /// ```
+            #[allow(unused_parens)]
+            pub fn retain<F>(&mut self, mut f: F)
+            where
+                F: FnMut(::core::any::TypeId, &mut (dyn ::core::any::Any $($bound)*)) -> bool,
+            {
+                use ::core::any::{Any, TypeId};
+                use $crate::SmallBox;
+                use $crate::storage::Storage;
+
+                // `f` cannot replace the boxes, only mutate the values in place.
+                Storage::retain(
+                    unsafe { self.inner.as_storage_mut() },
+                    |type_id: TypeId, boxed_any: &mut SmallBox<dyn Any $($bound)*>| {
+                        f(type_id, boxed_any.as_mut())
+                    },
+                );
+
+                #[cfg(debug_assertions)]
+                self.debug_type_names
+                    .retain(|type_id: &TypeId, _| Storage::contains_key(self.inner.as_storage(), type_id));
+            }
+
+            /// Creates an iterator which uses a closure to determine if a value should be
+            /// removed.
+            ///
+            /// If the closure returns `true`, the value is removed from the set and yielded.
+            /// If the closure returns `false`, the value remains in the set and will not be
+            /// yielded.
+            ///
+            /// If the returned iterator is not exhausted, e.g. because it is dropped without
+            /// iterating or the iteration short-circuits, then the remaining values are retained.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// set.insert(1_u32);
+            /// let extracted: Vec<_> = set.extract_if(|_, value| value.is::<u32>()).collect();
+            /// assert_eq!(extracted.len(), 1);
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u32>());
             /// ```
+            #[allow(unused_parens)]
+            pub fn extract_if<F>(
+                &mut self,
+                pred: F,
+            ) -> $crate::ExtractIf<'_, $storage, F>
+            where
+                F: FnMut(::core::any::TypeId, &mut (dyn ::core::any::Any $($bound)*)) -> bool,
+            {
+                $crate::ExtractIf::new(
+                    // The iterator only removes values or mutates them in place.
+                    unsafe { self.inner.as_storage_mut() },
+                    #[cfg(debug_assertions)]
+                    Some(&mut self.debug_type_names),
+                    #[cfg(not(debug_assertions))]
+                    None,
+                    pred,
+                )
+            }
+
+            /// Returns `true` if the set contains an instance of `T`.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
@@ -177,7 +629,7 @@ macro_rules! impl_erased_set {
             where
                 T: ::core::any::Any,
             {
-                self.inner.contains_key(&::core::any::TypeId::of::<T>())
+                $crate::storage::Storage::contains_key(self.inner.as_storage(), &::core::any::TypeId::of::<T>())
             }
 
             /// Returns a reference to an instance of `T`.
@@ -186,7 +638,7 @@ macro_rules! impl_erased_set {
             ///
             /// # Examples
             ///
-            /// ```
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
@@ -197,16 +649,16 @@ macro_rules! impl_erased_set {
             #[must_use]
             pub fn get<T>(&self) -> Option<&T>
             where
-                T: ::core::any::Any $(+ $bounds)*,
+                T: ::core::any::Any $($bound)*,
             {
                 use ::core::any::{Any, TypeId};
-                use ::alloc::boxed::Box;
+                use $crate::SmallBox;
+                use $crate::storage::Storage;
 
-                self.inner
-                    .get(&TypeId::of::<T>())
-                    .map(|boxed_any: &Box<dyn Any $(+ $bounds)*>| {
+                Storage::get(self.inner.as_storage(), &TypeId::of::<T>())
+                    .map(|boxed_any: &SmallBox<dyn Any $($bound)*>| {
                         // Sanity check
-                        debug_assert!(boxed_any.as_ref().is::<T>());
+                        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
 
                         let ptr = (boxed_any.as_ref() as *const dyn Any).cast::<T>();
 
@@ -219,7 +671,7 @@ macro_rules! impl_erased_set {
             ///
             /// # Examples
             ///
-            /// ```
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
@@ -228,25 +680,9 @@ macro_rules! impl_erased_set {
             /// ```
             pub fn get_or_insert<T>(&mut self, value: T) -> &T
             where
-                T: ::core::any::Any $(+ $bounds)*,
+                T: ::core::any::Any $($bound)* $(+ $($cap_bound)*)*,
             {
-                use ::core::any::{Any, TypeId};
-                use ::alloc::boxed::Box;
-
-                #[cfg(debug_assertions)]
-                self.debug_type_names.insert(TypeId::of::<T>(), core::any::type_name::<T>());
-
-                let boxed_any: &Box<dyn Any $(+ $bounds)*> = self
-                    .inner
-                    .entry(TypeId::of::<T>())
-                    .or_insert_with(|| Box::new(value));
-
-                // Sanity check
-                debug_assert!(boxed_any.as_ref().is::<T>());
-
-                let ptr = (boxed_any.as_ref() as *const dyn Any).cast::<T>();
-
-                unsafe { &*ptr }
+                self.entry::<T>().or_insert(value)
             }
 
             /// Inserts a value computed from `f` into the set if it does not contain
@@ -254,7 +690,7 @@ macro_rules! impl_erased_set {
             ///
             /// # Examples
             ///
-            /// ```
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
@@ -263,25 +699,44 @@ macro_rules! impl_erased_set {
             /// ```
             pub fn get_or_insert_with<T>(&mut self, f: impl FnOnce() -> T) -> &T
             where
-                T: ::core::any::Any $(+ $bounds)*,
+                T: ::core::any::Any $($bound)* $(+ $($cap_bound)*)*,
             {
-                use ::core::any::{Any, TypeId};
-                use ::alloc::boxed::Box;
-
-                #[cfg(debug_assertions)]
-                self.debug_type_names.insert(TypeId::of::<T>(), core::any::type_name::<T>());
-
-                let boxed_any: &Box<dyn Any $(+ $bounds)*> = self
-                    .inner
-                    .entry(TypeId::of::<T>())
-                    .or_insert_with(|| Box::new(f()));
+                self.entry::<T>().or_insert_with(f)
+            }
 
-                // Sanity check
-                debug_assert!(boxed_any.as_ref().is::<T>());
+            /// Gets the entry for type `T` in the set for in-place manipulation.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// *set.entry::<u32>().or_insert(0) += 1;
+            /// *set.entry::<u32>().or_insert(0) += 1;
+            /// assert_eq!(set.get::<u32>(), Some(&2));
+            /// ```
+            pub fn entry<T>(&mut self) -> $crate::Entry<'_, T, $storage>
+            where
+                T: ::core::any::Any $($bound)* $(+ $($cap_bound)*)*,
+            {
+                use ::core::any::{Any, TypeId};
+                use $crate::SmallBox;
+                use $crate::storage::Storage;
 
-                let ptr = (boxed_any.as_ref() as *const dyn Any).cast::<T>();
+                let into_boxed: fn(T) -> SmallBox<dyn Any $($bound)*> = Self::small_box;
 
-                unsafe { &*ptr }
+                // The entry of `T` holds an instance of `T`, if occupied.
+                unsafe {
+                    $crate::Entry::new(
+                        Storage::entry(self.inner.as_storage_mut(), TypeId::of::<T>()),
+                        #[cfg(debug_assertions)]
+                        Some(&mut self.debug_type_names),
+                        #[cfg(not(debug_assertions))]
+                        None,
+                        into_boxed,
+                    )
+                }
             }
 
             /// Returns a mutable reference to an instance of `T`.
@@ -290,7 +745,7 @@ macro_rules! impl_erased_set {
             ///
             /// # Examples
             ///
-            /// ```
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
@@ -303,16 +758,17 @@ macro_rules! impl_erased_set {
             #[must_use]
             pub fn get_mut<T>(&mut self) -> Option<&mut T>
             where
-                T: ::core::any::Any $(+ $bounds)*,
+                T: ::core::any::Any $($bound)*,
             {
                 use ::core::any::{Any, TypeId};
-                use ::alloc::boxed::Box;
+                use $crate::SmallBox;
+                use $crate::storage::Storage;
 
-                self.inner
-                    .get_mut(&TypeId::of::<T>())
-                    .map(|boxed_any: &mut Box<dyn Any $(+ $bounds)*>| {
+                // The value is only mutated in place.
+                unsafe { Storage::get_mut(self.inner.as_storage_mut(), &TypeId::of::<T>()) }
+                    .map(|boxed_any: &mut SmallBox<dyn Any $($bound)*>| {
                         // Sanity check
-                        debug_assert!(boxed_any.as_mut().is::<T>());
+                        debug_assert_eq!(Any::type_id(boxed_any.as_mut()), TypeId::of::<T>());
 
                         let ptr = (boxed_any.as_mut() as *mut dyn Any).cast::<T>();
 
@@ -320,93 +776,867 @@ macro_rules! impl_erased_set {
                     })
             }
 
-            /// Insert an instance of type `T` into the set.
+            /// Returns references to an instance of each type in the tuple `Q`.
             ///
-            /// Returns the replaced value or [`None`].
+            /// If the set does not have an instance of one of the types, [`None`] is returned.
             ///
             /// # Examples
             ///
-            /// ```
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
-            /// assert_eq!(set.insert("a"), None);
-            /// assert_eq!(set.insert("b"), Some("a"));
+            /// set.insert("a");
+            /// set.insert(1_u32);
+            /// assert_eq!(set.get_many::<(&str, u32)>(), Some((&"a", &1)));
+            /// assert_eq!(set.get_many::<(&str, bool)>(), None);
             /// ```
-            pub fn insert<T>(&mut self, value: T) -> Option<T>
+            #[must_use]
+            pub fn get_many<'a, Q>(&'a self) -> Option<Q::Refs>
             where
-                T: ::core::any::Any $(+ $bounds)*,
+                Q: $crate::TypeTuple<'a>,
             {
                 use ::core::any::{Any, TypeId};
-                use ::alloc::boxed::Box;
-
-                #[cfg(debug_assertions)]
-                self.debug_type_names.insert(TypeId::of::<T>(), core::any::type_name::<T>());
+                use $crate::SmallBox;
+                use $crate::storage::Storage;
 
-                self.inner
-                    .insert(TypeId::of::<T>(), Box::new(value))
-                    .map(|boxed_any: Box<dyn Any $(+ $bounds)*>| {
-                        // Sanity check
-                        debug_assert!(boxed_any.as_ref().is::<T>());
+                let get = |type_id: TypeId| {
+                    Storage::get(self.inner.as_storage(), &type_id)
+                        .map(|boxed_any: &SmallBox<dyn Any $($bound)*>| {
+                            boxed_any.as_ref() as *const dyn Any
+                        })
+                };
 
-                        let ptr = Box::into_raw(boxed_any).cast::<T>();
-
-                        unsafe { *Box::from_raw(ptr) }
-                    })
+                unsafe { Q::get_many(get) }
             }
 
-            /// Remove and return an instance of type `T` from the set.
+            /// Returns mutable references to an instance of each type in the tuple `Q`.
             ///
-            /// If the set did not have this type present, [`None`] is returned.
+            /// If the set does not have an instance of one of the types, [`None`] is returned.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `Q` contains the same type more than once.
             ///
             /// # Examples
             ///
-            /// ```
+            #[doc = concat!("```", $fence)]
             #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
             ///
             #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
             /// set.insert("a");
-            /// assert_eq!(set.remove::<&str>(), Some("a"));
+            /// set.insert(1_u32);
+            /// if let Some((x, y)) = set.get_many_mut::<(&str, u32)>() {
+            ///     *x = "b";
+            ///     *y += 1;
+            /// }
+            /// assert_eq!(set.get_many::<(&str, u32)>(), Some((&"b", &2)));
             /// ```
-            pub fn remove<T>(&mut self) -> Option<T>
+            #[must_use]
+            pub fn get_many_mut<'a, Q>(&'a mut self) -> Option<Q::Muts>
             where
-                T: ::core::any::Any $(+ $bounds)*,
+                Q: $crate::TypeTuple<'a>,
             {
-                use ::core::any::{Any, TypeId};
-                use ::alloc::boxed::Box;
-
-                #[cfg(debug_assertions)]
-                self.debug_type_names.remove(&TypeId::of::<T>());
-
-                self.inner
-                    .remove(&TypeId::of::<T>())
-                    .map(|boxed_any: Box<dyn Any $(+ $bounds)*>| {
-                        // Sanity check
-                        debug_assert!(boxed_any.as_ref().is::<T>());
+                use ::core::any::Any;
+                use $crate::SmallBox;
 
-                        let ptr = Box::into_raw(boxed_any).cast::<T>();
+                let as_ptr = |boxed_any: &'a mut SmallBox<dyn Any $($bound)*>| {
+                    boxed_any.as_mut() as *mut dyn Any
+                };
 
-                        unsafe { *Box::from_raw(ptr) }
-                    })
+                unsafe { Q::get_many_mut(self.inner.as_storage_mut(), as_ptr) }
             }
 
-            /// Gets an iterator over the [`TypeId`](::core::any::TypeId)s of stored elements, in arbitrary order.
+            /// Insert an instance of type `T` into the set.
+            ///
+            /// Returns the replaced value or [`None`].
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.insert("a"), None);
+            /// assert_eq!(set.insert("b"), Some("a"));
+            /// ```
+            pub fn insert<T>(&mut self, value: T) -> Option<T>
+            where
+                T: ::core::any::Any $($bound)* $(+ $($cap_bound)*)*,
+            {
+                self.insert_boxed(Self::small_box(value))
+            }
+
+            /// Insert an instance of type `T` into the set, keeping its
+            /// [`Debug`](::core::fmt::Debug) implementation.
+            ///
+            /// The value is then shown by the `Debug` output of the set, next to its type name.
+            /// Values inserted by other methods only show their type name.
+            ///
+            /// Returns the replaced value or [`None`].
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.insert_debug(1_u32), None);
+            /// assert_eq!(set.insert_debug(2_u32), Some(1));
+            /// assert!(format!("{:?}", set).contains(": 2"));
+            /// ```
+            pub fn insert_debug<T>(&mut self, value: T) -> Option<T>
+            where
+                T: ::core::any::Any + ::core::fmt::Debug $($bound)* $(+ $($cap_bound)*)*,
+            {
+                let vtable = $crate::impl_erased_set! {
+                    @vtable T [$($bound)*] [
+                        $($cap ($($cap_bound)*))* with_debug (::core::fmt::Debug)
+                    ]
+                };
+
+                self.insert_boxed(unsafe { $crate::SmallBox::new(value, vtable) })
+            }
+
+            /// Remove and return an instance of type `T` from the set.
+            ///
+            /// If the set did not have this type present, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.remove::<&str>(), Some("a"));
+            /// ```
+            pub fn remove<T>(&mut self) -> Option<T>
+            where
+                T: ::core::any::Any $($bound)*,
+            {
+                use ::core::any::{Any, TypeId};
+                use $crate::SmallBox;
+
+                #[cfg(debug_assertions)]
+                self.debug_type_names.remove(&TypeId::of::<T>());
+
+                self.inner
+                    .remove(&TypeId::of::<T>())
+                    .map(|boxed_any: SmallBox<dyn Any $($bound)*>| {
+                        // Sanity check
+                        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+                        unsafe { boxed_any.into_inner_unchecked() }
+                    })
+            }
+
+            /// Moves all values of `other` into the set.
+            ///
+            /// A value replaces any previous value of the same type.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            ///
+            #[doc = concat!("let mut other = ", stringify!($name), "::new();")]
+            /// other.insert("b");
+            /// other.insert(1_u32);
+            ///
+            /// set.extend_from(other);
+            /// assert_eq!(set.get::<&str>(), Some(&"b"));
+            /// assert_eq!(set.get::<u32>(), Some(&1));
+            /// ```
+            pub fn extend_from(&mut self, other: Self) {
+                let _ = self.merge(other, $crate::MergePolicy::Overwrite);
+            }
+
+            /// Moves all values of `other` into the set, resolving the types present in both sets
+            /// with `policy`.
+            ///
+            /// Returns the types that were added, replaced and kept.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            /// use erased_set::MergePolicy;
+            /// use std::any::TypeId;
+            ///
+            #[doc = concat!("let mut defaults = ", stringify!($name), "::new();")]
+            /// defaults.insert("info");
+            /// defaults.insert(80_u16);
+            ///
+            #[doc = concat!("let mut overrides = ", stringify!($name), "::new();")]
+            /// overrides.insert("debug");
+            /// overrides.insert(true);
+            ///
+            /// let report = defaults.merge(overrides, MergePolicy::KeepExisting);
+            /// assert_eq!(report.added, [TypeId::of::<bool>()]);
+            /// assert_eq!(report.kept, [TypeId::of::<&str>()]);
+            /// assert_eq!(defaults.get::<&str>(), Some(&"info"));
+            /// ```
+            pub fn merge(&mut self, other: Self, policy: $crate::MergePolicy) -> $crate::MergeReport {
+                self.merge_entries(other, |_, existing, incoming| match policy {
+                    $crate::MergePolicy::KeepExisting => false,
+                    $crate::MergePolicy::Overwrite => {
+                        *existing = incoming;
+                        true
+                    }
+                })
+            }
+
+            /// Moves all values of `other` into the set, resolving the types present in both sets
+            /// with `resolve`.
+            ///
+            /// `resolve` receives the type id, the existing value and the value of `other`, and
+            /// updates the existing value in place, which is reported as replaced. If `resolve`
+            /// panics, the existing value is left in the set.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(vec!["a"]);
+            ///
+            #[doc = concat!("let mut other = ", stringify!($name), "::new();")]
+            /// other.insert(vec!["b"]);
+            ///
+            /// let report = set.merge_with(other, |_, existing, incoming| {
+            ///     if let (Some(existing), Some(incoming)) = (
+            ///         existing.downcast_mut::<Vec<&str>>(),
+            ///         incoming.downcast_ref::<Vec<&str>>(),
+            ///     ) {
+            ///         existing.extend(incoming);
+            ///     }
+            /// });
+            /// assert_eq!(report.replaced.len(), 1);
+            /// assert_eq!(set.get::<Vec<&str>>(), Some(&vec!["a", "b"]));
+            /// ```
+            #[allow(unused_parens)]
+            pub fn merge_with<F>(&mut self, other: Self, mut resolve: F) -> $crate::MergeReport
+            where
+                F: FnMut(
+                    ::core::any::TypeId,
+                    &mut (dyn ::core::any::Any $($bound)*),
+                    $crate::SmallBox<dyn ::core::any::Any $($bound)*>,
+                ),
+            {
+                // The existing value is only borrowed, so that it cannot be replaced by a value
+                // of another type, or without the vtable of the set.
+                self.merge_entries(other, |type_id, existing, incoming| {
+                    resolve(type_id, &mut **existing, incoming);
+                    true
+                })
+            }
+
+            /// Applies the mutations staged by `f` at once, e.g. to update several types that
+            /// must stay consistent.
+            ///
+            /// The mutations are applied once `f` returns `Ok`. If `f` returns `Err` or panics,
+            /// they are discarded and the set is left untouched.
+            ///
+            /// Returns the values removed or replaced by the transaction.
+            ///
+            /// # Errors
+            ///
+            /// Returns the error of `f`.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("localhost");
+            /// set.insert(80_u16);
+            ///
+            /// let result = set.transaction(|tx| {
+            ///     tx.insert(8080_u16);
+            ///     tx.remove::<&str>();
+            ///     Err("invalid configuration")
+            /// });
+            /// assert!(result.is_err());
+            /// assert_eq!(set.get::<u16>(), Some(&80));
+            /// assert!(set.contains::<&str>());
+            ///
+            /// let replaced = set
+            ///     .transaction(|tx| {
+            ///         tx.insert(8080_u16);
+            ///         Ok::<_, ()>(())
+            ///     })
+            ///     .unwrap();
+            /// assert_eq!(set.get::<u16>(), Some(&8080));
+            /// assert_eq!(replaced.get::<u16>(), Some(&80));
+            /// ```
+            pub fn transaction<F, E>(&mut self, f: F) -> Result<Self, E>
+            where
+                F: FnOnce(&mut $crate::Transaction<'_, Self>) -> Result<(), E>,
+            {
+                let mut transaction = $crate::Transaction::new(&*self);
+                f(&mut transaction)?;
+
+                let (staged, removed) = transaction.into_parts();
+                Ok($crate::__private::Transactional::commit(self, staged, removed))
+            }
+
+            /// Gets an iterator over the stored elements and their [`TypeId`](::core::any::TypeId)s,
+            /// in arbitrary order.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// set.insert(1_u32);
+            /// assert_eq!(set.iter().count(), 2);
+            /// assert!(set.iter().any(|(_, value)| value.downcast_ref::<&str>() == Some(&"a")));
+            /// ```
+            pub fn iter(&self) -> $crate::Iter<'_, $storage> {
+                $crate::Iter::new($crate::storage::Storage::iter(self.inner.as_storage()))
+            }
+
+            /// Gets a mutable iterator over the stored elements and their
+            /// [`TypeId`](::core::any::TypeId)s, in arbitrary order.
+            ///
+            /// # Examples
+            ///
+            #[doc = concat!("```", $fence)]
+            #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// set.insert(1_u32);
+            /// for (_, value) in set.iter_mut() {
+            ///     if let Some(x) = value.downcast_mut::<u32>() {
+            ///         *x += 1;
+            ///     }
+            /// }
+            /// assert_eq!(set.get::<u32>(), Some(&2));
+            /// ```
+            pub fn iter_mut(&mut self) -> $crate::IterMut<'_, $storage> {
+                // The values are only mutated in place.
+                $crate::IterMut::new($crate::storage::Storage::iter_mut(unsafe {
+                    self.inner.as_storage_mut()
+                }))
+            }
+
+            /// Gets an iterator over the [`TypeId`](::core::any::TypeId)s of stored elements, in arbitrary order.
             pub fn type_ids(&self) -> impl Iterator<Item = &::core::any::TypeId> {
-                self.inner.keys()
+                $crate::storage::Storage::iter(self.inner.as_storage()).map(|(type_id, _)| type_id)
             }
 
             /// Gets an iterator over the names of the stored types, in arbitrary order.
             #[cfg(debug_assertions)]
             pub fn debug_type_names(&self) -> impl Iterator<Item = &'static str> + '_ {
-                assert!(self.inner.keys().eq(self.debug_type_names.keys()));
+                assert!(
+                    self.len() == self.debug_type_names.len()
+                        && self.debug_type_names.keys().all(|type_id: &::core::any::TypeId| {
+                            $crate::storage::Storage::contains_key(self.inner.as_storage(), type_id)
+                        })
+                );
 
                 self.debug_type_names.values().map(|&name: &&'static str| name)
             }
+
+            fn merge_entries<F>(&mut self, other: Self, mut resolve: F) -> $crate::MergeReport
+            where
+                F: FnMut(
+                    ::core::any::TypeId,
+                    &mut $crate::SmallBox<dyn ::core::any::Any $($bound)*>,
+                    $crate::SmallBox<dyn ::core::any::Any $($bound)*>,
+                ) -> bool,
+            {
+                use $crate::storage::Storage;
+
+                let mut report = $crate::MergeReport::default();
+
+                for (type_id, incoming) in Storage::into_iter(other.inner.into_storage()) {
+                    #[cfg(debug_assertions)]
+                    let name = other.debug_type_names[&type_id];
+
+                    // `incoming` is stored under its own type id, in `other`, and `resolve` only
+                    // replaces the existing value with it. The existing value stays in the
+                    // storage, even if `resolve` panics.
+                    let storage = unsafe { self.inner.as_storage_mut() };
+
+                    match Storage::get_mut(storage, &type_id) {
+                        None => {
+                            Storage::insert(storage, type_id, incoming);
+                            report.added.push(type_id);
+
+                            #[cfg(debug_assertions)]
+                            self.debug_type_names.insert(type_id, name);
+                        }
+                        Some(existing) => {
+                            if resolve(type_id, existing, incoming) {
+                                report.replaced.push(type_id);
+                            } else {
+                                report.kept.push(type_id);
+                            }
+                        }
+                    }
+                }
+
+                report
+            }
+
+            fn insert_boxed<T>(
+                &mut self,
+                boxed_any: $crate::SmallBox<dyn ::core::any::Any $($bound)*>,
+            ) -> Option<T>
+            where
+                T: ::core::any::Any $($bound)*,
+            {
+                use ::core::any::{Any, TypeId};
+                use $crate::SmallBox;
+                use $crate::storage::Storage;
+
+                #[cfg(debug_assertions)]
+                self.debug_type_names.insert(TypeId::of::<T>(), ::core::any::type_name::<T>());
+
+                // The box holds a `T`, as required by the callers.
+                unsafe { Storage::insert(self.inner.as_storage_mut(), TypeId::of::<T>(), boxed_any) }
+                    .map(|boxed_any: SmallBox<dyn Any $($bound)*>| {
+                        // Sanity check
+                        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+                        unsafe { boxed_any.into_inner_unchecked() }
+                    })
+            }
+
+            fn small_box<T>(value: T) -> $crate::SmallBox<dyn ::core::any::Any $($bound)*>
+            where
+                T: ::core::any::Any $($bound)* $(+ $($cap_bound)*)*,
+            {
+                let vtable = $crate::impl_erased_set! {
+                    @vtable T [$($bound)*] [$($cap ($($cap_bound)*))*]
+                };
+
+                unsafe { $crate::SmallBox::new(value, vtable) }
+            }
         }
-    }
+
+        /// Values inserted with `insert_debug` are shown next to their type name, other values
+        /// only show their type name.
+        impl ::core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                use ::core::any::{Any, TypeId};
+                use $crate::SmallBox;
+                use $crate::storage::Storage;
+
+                f.debug_set()
+                    .entries(Storage::iter(self.inner.as_storage()).map(
+                        |(type_id, boxed_any): (&TypeId, &SmallBox<dyn Any $($bound)*>)| {
+                            $crate::__private::DebugEntry {
+                                #[cfg(debug_assertions)]
+                                key: self.debug_type_names[type_id],
+                                #[cfg(not(debug_assertions))]
+                                key: type_id,
+                                value: boxed_any.as_debug(),
+                            }
+                        },
+                    ))
+                    .finish()
+            }
+        }
+
+        impl<'a> ::core::iter::IntoIterator for &'a $name {
+            type Item = <Self::IntoIter as ::core::iter::Iterator>::Item;
+            type IntoIter = $crate::Iter<'a, $storage>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl<'a> ::core::iter::IntoIterator for &'a mut $name {
+            type Item = <Self::IntoIter as ::core::iter::Iterator>::Item;
+            type IntoIter = $crate::IterMut<'a, $storage>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter_mut()
+            }
+        }
+
+        impl ::core::iter::IntoIterator for $name {
+            type Item = $crate::__private::Box<dyn ::core::any::Any $($bound)*>;
+            type IntoIter = $crate::IntoIter<$storage>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                $crate::IntoIter::new($crate::storage::Storage::into_iter(self.inner.into_storage()))
+            }
+        }
+
+        impl $crate::__private::Transactional for $name {
+            fn staging() -> Self {
+                Self::new()
+            }
+
+            fn get_any(
+                &self,
+                type_id: &::core::any::TypeId,
+            ) -> Option<&dyn ::core::any::Any> {
+                use ::core::any::Any;
+                use $crate::SmallBox;
+                use $crate::storage::Storage;
+
+                Storage::get(self.inner.as_storage(), type_id)
+                    .map(|boxed_any: &SmallBox<dyn Any $($bound)*>| boxed_any.as_ref() as &dyn Any)
+            }
+
+            fn type_name(&self, type_id: &::core::any::TypeId) -> Option<&'static str> {
+                #[cfg(debug_assertions)]
+                return self.debug_type_names.get(type_id).copied();
+
+                #[cfg(not(debug_assertions))]
+                return {
+                    let _ = type_id;
+                    None
+                };
+            }
+
+            fn discard(&mut self, type_id: &::core::any::TypeId) {
+                #[cfg(debug_assertions)]
+                self.debug_type_names.remove(type_id);
+
+                let _ = self.inner.remove(type_id);
+            }
+
+            fn commit(
+                &mut self,
+                staged: Self,
+                removed: $crate::__private::BTreeSet<::core::any::TypeId>,
+            ) -> Self {
+                use $crate::storage::Storage;
+
+                let mut replaced = Self::new();
+
+                // The values move between sets under the type ids they were stored under.
+                let storage = unsafe { self.inner.as_storage_mut() };
+                let replaced_storage = unsafe { replaced.inner.as_storage_mut() };
+
+                for type_id in removed {
+                    if let Some(value) = Storage::remove(storage, &type_id) {
+                        Storage::insert(replaced_storage, type_id, value);
+
+                        #[cfg(debug_assertions)]
+                        replaced
+                            .debug_type_names
+                            .insert(type_id, self.debug_type_names.remove(&type_id).unwrap());
+                    }
+                }
+
+                for (type_id, value) in Storage::into_iter(staged.inner.into_storage()) {
+                    #[cfg(debug_assertions)]
+                    let name = staged.debug_type_names[&type_id];
+
+                    if let Some(value) = Storage::insert(storage, type_id, value) {
+                        Storage::insert(replaced_storage, type_id, value);
+
+                        #[cfg(debug_assertions)]
+                        replaced
+                            .debug_type_names
+                            .insert(type_id, self.debug_type_names[&type_id]);
+                    }
+
+                    #[cfg(debug_assertions)]
+                    self.debug_type_names.insert(type_id, name);
+                }
+
+                replaced
+            }
+        }
+
+        impl<T> $crate::__private::Stage<T> for $name
+        where
+            T: ::core::any::Any $($bound)* $(+ $($cap_bound)*)*,
+        {
+            fn stage(&mut self, value: T) {
+                let _ = self.insert(value);
+            }
+        }
+
+        $crate::impl_erased_set! { @from_boxes $fence [$($cap)*] [$($bound)*] $name }
+
+        $($crate::impl_erased_set! { @$cap $fence $name })*
+    };
+    (
+        @impl $fence:literal
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $($rest:tt)*
+    ) => {
+        $crate::impl_erased_set! {
+            @parse $fence [$(#[$attr])* $vis struct $name] [] [] $($rest)*
+        }
+    };
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $($rest:tt)*
+    ) => {
+        // The examples refer to the set through `module_path!()`, which may not be reachable from
+        // the doctests of the invoking crate.
+        $crate::impl_erased_set! {
+            @parse "ignore" [$(#[$attr])* $vis struct $name] [] [] $($rest)*
+        }
+    };
+    // A `&'static Vtable` for `$ty` with the given capabilities.
+    (@vtable $ty:ident [$($bound:tt)*] [$($cap:ident ($($cap_bound:tt)*))*]) => {{
+        struct VtableOf<T>(::core::marker::PhantomData<T>);
+
+        impl<T> VtableOf<T>
+        where
+            T: ::core::any::Any $($bound)* $(+ $($cap_bound)*)*,
+        {
+            // The pointer is unsized to the trait object, keeping its address.
+            const VTABLE: &'static $crate::Vtable<dyn ::core::any::Any $($bound)*> =
+                &<$crate::Vtable<dyn ::core::any::Any $($bound)*>>::new(|ptr| ptr.cast::<T>())
+                    $(.$cap::<T>())*;
+        }
+
+        VtableOf::<$ty>::VTABLE
+    }};
+    (@from_boxes $fence:literal [] [$($bound:tt)*] $name:ident) => {
+        /// Values are keyed by their concrete type, a value replaces any previous value of the
+        /// same type.
+        ///
+        /// # Examples
+        ///
+        #[doc = concat!("```", $fence)]
+        #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+        ///
+        #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+        /// set.insert("a");
+        /// set.insert(1_u32);
+        ///
+        #[doc = concat!("let other: ", stringify!($name), " = set")]
+        ///     .into_iter()
+        ///     .filter(|boxed_any| !boxed_any.is::<u32>())
+        ///     .collect();
+        /// assert_eq!(other.get::<&str>(), Some(&"a"));
+        /// assert_eq!(other.get::<u32>(), None);
+        /// ```
+        impl ::core::iter::FromIterator<$crate::__private::Box<dyn ::core::any::Any $($bound)*>>
+            for $name
+        {
+            fn from_iter<I>(iter: I) -> Self
+            where
+                I: ::core::iter::IntoIterator<
+                    Item = $crate::__private::Box<dyn ::core::any::Any $($bound)*>,
+                >,
+            {
+                let mut set = Self::new();
+                set.extend(iter);
+                set
+            }
+        }
+
+        /// Values are keyed by their concrete type, a value replaces any previous value of the
+        /// same type.
+        impl ::core::iter::Extend<$crate::__private::Box<dyn ::core::any::Any $($bound)*>>
+            for $name
+        {
+            fn extend<I>(&mut self, iter: I)
+            where
+                I: ::core::iter::IntoIterator<
+                    Item = $crate::__private::Box<dyn ::core::any::Any $($bound)*>,
+                >,
+            {
+                use ::core::any::Any;
+                use $crate::storage::Storage;
+
+                for boxed_any in iter {
+                    let type_id = Any::type_id(boxed_any.as_ref());
+
+                    // The concrete type name is not known past this point.
+                    #[cfg(debug_assertions)]
+                    self.debug_type_names
+                        .insert(type_id, ::core::any::type_name::<$crate::__private::Box<dyn Any $($bound)*>>());
+
+                    // Stored under the type id of the value.
+                    unsafe { Storage::insert(self.inner.as_storage_mut(), type_id, boxed_any.into()) };
+                }
+            }
+        }
+    };
+    // The capabilities of a boxed value cannot be captured, its concrete type is unknown.
+    (@from_boxes $fence:literal [$($cap:tt)+] $bounds:tt $name:ident) => {};
+    (@with_clone $fence:literal $name:ident) => {
+        /// Every value is cloned with the [`Clone`] implementation of its type.
+        ///
+        /// # Examples
+        ///
+        #[doc = concat!("```", $fence)]
+        #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+        ///
+        #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+        /// set.insert(String::from("a"));
+        ///
+        /// let mut other = set.clone();
+        /// other.get_mut::<String>().unwrap().push('b');
+        /// assert_eq!(set.get::<String>().map(String::as_str), Some("a"));
+        /// assert_eq!(other.get::<String>().map(String::as_str), Some("ab"));
+        /// ```
+        impl ::core::clone::Clone for $name {
+            fn clone(&self) -> Self {
+                use $crate::storage::Storage;
+
+                let mut set = Self::with_capacity(self.len());
+
+                for (&type_id, boxed_any) in Storage::iter(self.inner.as_storage()) {
+                    let boxed_any = boxed_any
+                        .try_clone()
+                        .expect("values are inserted with a clone function");
+
+                    // A clone has the type of the value it was cloned from.
+                    unsafe { Storage::insert(set.inner.as_storage_mut(), type_id, boxed_any) };
+                }
+
+                #[cfg(debug_assertions)]
+                set.debug_type_names.clone_from(&self.debug_type_names);
+
+                set
+            }
+        }
+    };
+    (@with_partial_eq $fence:literal $name:ident) => {
+        /// Two sets are equal if they contain the same types and the values of each type are
+        /// equal.
+        ///
+        /// # Examples
+        ///
+        #[doc = concat!("```", $fence)]
+        #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+        ///
+        #[doc = concat!("let mut a = ", stringify!($name), "::new();")]
+        /// a.insert("a");
+        /// a.insert(1_u32);
+        ///
+        #[doc = concat!("let mut b = ", stringify!($name), "::new();")]
+        /// b.insert(1_u32);
+        /// b.insert("a");
+        /// assert_eq!(a, b);
+        ///
+        /// b.insert(2_u32);
+        /// assert_ne!(a, b);
+        /// ```
+        impl ::core::cmp::PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                use $crate::storage::Storage;
+
+                self.len() == other.len()
+                    && Storage::iter(self.inner.as_storage()).all(|(type_id, boxed_any)| {
+                        Storage::get(other.inner.as_storage(), type_id).map_or(false, |other_boxed_any| {
+                            // The values are stored under the same type id.
+                            unsafe { boxed_any.try_eq(other_boxed_any) }
+                                .expect("values are inserted with an eq function")
+                        })
+                    })
+            }
+        }
+    };
+    (@with_eq $fence:literal $name:ident) => {
+        impl ::core::cmp::Eq for $name {}
+    };
+    (@with_hash $fence:literal $name:ident) => {
+        /// The hash does not depend on the order of the storage, equal sets have the same hash.
+        ///
+        /// # Examples
+        ///
+        #[doc = concat!("```", $fence)]
+        #[doc = concat!("use ", module_path!(), "::", stringify!($name), ";")]
+        /// use std::collections::hash_map::DefaultHasher;
+        /// use std::hash::{Hash, Hasher};
+        ///
+        #[doc = concat!("fn hash(set: &", stringify!($name), ") -> u64 {")]
+        ///     let mut hasher = DefaultHasher::new();
+        ///     set.hash(&mut hasher);
+        ///     hasher.finish()
+        /// }
+        ///
+        #[doc = concat!("let mut a = ", stringify!($name), "::new();")]
+        /// a.insert("a");
+        /// a.insert(1_u32);
+        ///
+        #[doc = concat!("let mut b = ", stringify!($name), "::new();")]
+        /// b.insert(1_u32);
+        /// b.insert("a");
+        /// assert_eq!(hash(&a), hash(&b));
+        /// ```
+        impl ::core::hash::Hash for $name {
+            fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                use ::core::hash::Hash;
+                use $crate::storage::Storage;
+
+                let mut entries: $crate::__private::Vec<_> = Storage::iter(self.inner.as_storage()).collect();
+                entries.sort_unstable_by_key(|&(type_id, _)| type_id);
+
+                state.write_usize(entries.len());
+
+                for (type_id, boxed_any) in entries {
+                    type_id.hash(state);
+
+                    let hashed = boxed_any.try_hash(state);
+                    assert!(hashed, "values are inserted with a hash function");
+                }
+            }
+        }
+    };
+    // These traits are not auto traits, their implementation is captured per value instead.
+    (@parse $fence:literal $decl:tt [$($bound:tt)*] [$($cap:tt)*] + Clone $($rest:tt)*) => {
+        $crate::impl_erased_set! {
+            @parse $fence $decl [$($bound)*] [$($cap)* with_clone (::core::clone::Clone)] $($rest)*
+        }
+    };
+    (@parse $fence:literal $decl:tt [$($bound:tt)*] [$($cap:tt)*] + PartialEq $($rest:tt)*) => {
+        $crate::impl_erased_set! {
+            @parse $fence $decl [$($bound)*] [$($cap)* with_partial_eq (::core::cmp::PartialEq)]
+            $($rest)*
+        }
+    };
+    (@parse $fence:literal $decl:tt [$($bound:tt)*] [$($cap:tt)*] + Eq $($rest:tt)*) => {
+        $crate::impl_erased_set! {
+            @parse $fence $decl [$($bound)*] [$($cap)* with_eq (::core::cmp::Eq)] $($rest)*
+        }
+    };
+    (@parse $fence:literal $decl:tt [$($bound:tt)*] [$($cap:tt)*] + Hash $($rest:tt)*) => {
+        $crate::impl_erased_set! {
+            @parse $fence $decl [$($bound)*] [$($cap)* with_hash (::core::hash::Hash)] $($rest)*
+        }
+    };
+    (@parse $fence:literal $decl:tt [$($bound:tt)*] $caps:tt + $segment:ident $($rest:tt)*) => {
+        $crate::impl_erased_set! { @parse $fence $decl [$($bound)* + $segment] $caps $($rest)* }
+    };
+    (@parse $fence:literal $decl:tt [$($bound:tt)*] $caps:tt :: $segment:ident $($rest:tt)*) => {
+        $crate::impl_erased_set! { @parse $fence $decl [$($bound)* :: $segment] $caps $($rest)* }
+    };
+    (@parse $fence:literal $decl:tt $bounds:tt $caps:tt;) => {
+        $crate::impl_erased_set! {
+            @parse $fence $decl $bounds $caps in $crate::storage::BTreeStorage;
+        }
+    };
+    (
+        @parse $fence:literal $decl:tt [$($bound:tt)*] $caps:tt
+        in $($storage:ident)::+;
+    ) => {
+        $crate::impl_erased_set! {
+            @impl $fence [
+                $($storage)::+<$crate::SmallBox<dyn ::core::any::Any $($bound)*>>
+            ] [$($bound)*] $caps $decl
+        }
+    };
 }
 
 impl_erased_set! {
+    @impl ""
     /// A set of erased types.
     ///
     /// This set can store a single instance of any type that implements [`Any`](::core::any::Any).
@@ -437,21 +1667,9 @@ impl_erased_set! {
     pub struct ErasedSet: Any;
 }
 
-impl core::fmt::Debug for ErasedSet {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_set()
-            .entries(
-                #[cfg(debug_assertions)]
-                self.debug_type_names(),
-                #[cfg(not(debug_assertions))]
-                self.type_ids(),
-            )
-            .finish()
-    }
-}
-
 #[cfg(feature = "send")]
 impl_erased_set! {
+    @impl ""
     /// Like [`ErasedSet`] but with a [`Send`] bound.
     ///
     /// ## Example
@@ -480,22 +1698,9 @@ impl_erased_set! {
     pub struct ErasedSendSet: Any + Send;
 }
 
-#[cfg(feature = "send")]
-impl core::fmt::Debug for ErasedSendSet {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_set()
-            .entries(
-                #[cfg(debug_assertions)]
-                self.debug_type_names(),
-                #[cfg(not(debug_assertions))]
-                self.type_ids(),
-            )
-            .finish()
-    }
-}
-
 #[cfg(feature = "sync")]
 impl_erased_set! {
+    @impl ""
     /// Like [`ErasedSet`] but with a [`Send`] + [`Sync`] bound.
     ///
     /// ## Example
@@ -524,16 +1729,72 @@ impl_erased_set! {
     pub struct ErasedSyncSet: Any + Send + Sync;
 }
 
+impl_erased_set! {
+    @impl ""
+    /// Like [`ErasedSet`] but with a [`Clone`] bound, the set itself implements [`Clone`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # #[derive(Clone, Debug, PartialEq)]
+    /// # struct Locale(&'static str);
+    /// # #[derive(Clone, Debug, PartialEq)]
+    /// # struct Timeout(u64);
+    /// #
+    /// use erased_set::ErasedCloneSet;
+    ///
+    /// let mut defaults = ErasedCloneSet::new();
+    /// defaults.insert(Locale("en"));
+    /// defaults.insert(Timeout(30));
+    ///
+    /// let mut tenant = defaults.clone();
+    /// tenant.insert(Locale("fr"));
+    ///
+    /// assert_eq!(defaults.get::<Locale>(), Some(&Locale("en")));
+    /// assert_eq!(tenant.get::<Locale>(), Some(&Locale("fr")));
+    /// assert_eq!(tenant.get::<Timeout>(), Some(&Timeout(30)));
+    /// ```
+    #[derive(Default)]
+    pub struct ErasedCloneSet: Any + Clone;
+}
+
+#[cfg(feature = "send")]
+impl_erased_set! {
+    @impl ""
+    /// Like [`ErasedCloneSet`] but with a [`Send`] bound.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedSendCloneSet;
+    ///
+    /// let mut set = ErasedSendCloneSet::new();
+    /// set.insert(String::from("a"));
+    ///
+    /// let other = set.clone();
+    /// std::thread::spawn(move || assert_eq!(other.len(), 1)).join().unwrap();
+    /// ```
+    #[derive(Default)]
+    pub struct ErasedSendCloneSet: Any + Clone + Send;
+}
+
 #[cfg(feature = "sync")]
-impl core::fmt::Debug for ErasedSyncSet {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_set()
-            .entries(
-                #[cfg(debug_assertions)]
-                self.debug_type_names(),
-                #[cfg(not(debug_assertions))]
-                self.type_ids(),
-            )
-            .finish()
-    }
+impl_erased_set! {
+    @impl ""
+    /// Like [`ErasedCloneSet`] but with a [`Send`] + [`Sync`] bound.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedSyncCloneSet;
+    /// use std::sync::Arc;
+    ///
+    /// let mut set = ErasedSyncCloneSet::new();
+    /// set.insert(String::from("a"));
+    ///
+    /// let shared = Arc::new(set.clone());
+    /// std::thread::spawn(move || assert_eq!(shared.len(), 1)).join().unwrap();
+    /// ```
+    #[derive(Default)]
+    pub struct ErasedSyncCloneSet: Any + Clone + Send + Sync;
 }
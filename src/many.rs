@@ -0,0 +1,105 @@
+use crate::storage::Storage;
+use ::core::any::{Any, TypeId};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// A tuple of types that can be borrowed at once from an erased set.
+///
+/// This trait is implemented for tuples of up to 12 types, see the `get_many` and
+/// `get_many_mut` methods on the erased sets.
+pub trait TypeTuple<'a>: private::Sealed {
+    /// A tuple of shared references to each type.
+    type Refs;
+
+    /// A tuple of mutable references to each type.
+    type Muts;
+
+    /// Look up each type with `get` and cast the returned pointers.
+    ///
+    /// # Safety
+    ///
+    /// The pointers returned by `get` must point to instances of the requested type and be valid
+    /// for reads for `'a`.
+    #[doc(hidden)]
+    unsafe fn get_many(get: impl FnMut(TypeId) -> Option<*const dyn Any>) -> Option<Self::Refs>;
+
+    /// Look up each type in `storage` and cast the values returned by `as_ptr`.
+    ///
+    /// # Safety
+    ///
+    /// The pointers returned by `as_ptr` must point to instances of the requested type, inside
+    /// the value they were created from.
+    #[doc(hidden)]
+    unsafe fn get_many_mut<S: Storage>(
+        storage: &'a mut S,
+        as_ptr: impl FnMut(&'a mut S::Value) -> *mut dyn Any,
+    ) -> Option<Self::Muts>;
+}
+
+macro_rules! impl_type_tuple {
+    ($($ty:ident $ptr:ident),+) => {
+        impl<$($ty),+> private::Sealed for ($($ty,)+) {}
+
+        impl<'a, $($ty),+> TypeTuple<'a> for ($($ty,)+)
+        where
+            $($ty: Any,)+
+        {
+            type Refs = ($(&'a $ty,)+);
+            type Muts = ($(&'a mut $ty,)+);
+
+            unsafe fn get_many(
+                mut get: impl FnMut(TypeId) -> Option<*const dyn Any>,
+            ) -> Option<Self::Refs> {
+                $(
+                    let $ptr = get(TypeId::of::<$ty>())?;
+
+                    // Sanity check
+                    debug_assert!((*$ptr).is::<$ty>());
+                )+
+
+                Some(($(&*$ptr.cast::<$ty>(),)+))
+            }
+
+            unsafe fn get_many_mut<S: Storage>(
+                storage: &'a mut S,
+                mut as_ptr: impl FnMut(&'a mut S::Value) -> *mut dyn Any,
+            ) -> Option<Self::Muts> {
+                let type_ids = [$(TypeId::of::<$ty>()),+];
+
+                for (index, type_id) in type_ids.iter().enumerate() {
+                    assert!(
+                        !type_ids[..index].contains(type_id),
+                        "duplicate type in `get_many_mut`"
+                    );
+                }
+
+                // The storage hands out disjoint references, unlike repeated `get_mut` calls.
+                let [$($ptr),+] = storage.get_many_mut([$(&TypeId::of::<$ty>()),+]);
+
+                $(
+                    let $ptr = as_ptr($ptr?);
+
+                    // Sanity check
+                    debug_assert!((*$ptr).is::<$ty>());
+                )+
+
+                Some(($(&mut *$ptr.cast::<$ty>(),)+))
+            }
+        }
+    };
+}
+
+impl_type_tuple!(A a);
+impl_type_tuple!(A a, B b);
+impl_type_tuple!(A a, B b, C c);
+impl_type_tuple!(A a, B b, C c, D d);
+impl_type_tuple!(A a, B b, C c, D d, E e);
+impl_type_tuple!(A a, B b, C c, D d, E e, F f);
+impl_type_tuple!(A a, B b, C c, D d, E e, F f, G g);
+impl_type_tuple!(A a, B b, C c, D d, E e, F f, G g, H h);
+impl_type_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i);
+impl_type_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j);
+impl_type_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k);
+impl_type_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l);
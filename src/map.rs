@@ -0,0 +1,485 @@
+use ::alloc::collections::BTreeMap;
+use ::alloc::vec::Vec;
+use ::core::any::{Any, TypeId};
+use ::core::borrow::Borrow;
+use ::core::cmp::Ordering;
+use ::core::ops::Bound;
+
+use crate::SmallBox;
+
+/// Where a lookup falls among the types of a key.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    First,
+    Type(TypeId),
+    Last,
+}
+
+/// A `(K, TypeId)` key that can be compared without owning `K`.
+trait Query<K> {
+    fn key(&self) -> &K;
+    fn rank(&self) -> Rank;
+}
+
+impl<'a, K: Ord> PartialEq for dyn Query<K> + 'a {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a, K: Ord> Eq for dyn Query<K> + 'a {}
+
+impl<'a, K: Ord> PartialOrd for dyn Query<K> + 'a {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, K: Ord> Ord for dyn Query<K> + 'a {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.key(), self.rank()).cmp(&(other.key(), other.rank()))
+    }
+}
+
+/// The key of a value in an erased map.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Key<K> {
+    key: K,
+    type_id: TypeId,
+}
+
+impl<K> Query<K> for Key<K> {
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn rank(&self) -> Rank {
+        Rank::Type(self.type_id)
+    }
+}
+
+impl<'a, K: Ord + 'a> Borrow<dyn Query<K> + 'a> for Key<K> {
+    fn borrow(&self) -> &(dyn Query<K> + 'a) {
+        self
+    }
+}
+
+/// A borrowed [`Key`], or one of the bounds of the keys of a `K`.
+struct Lookup<'a, K> {
+    key: &'a K,
+    rank: Rank,
+}
+
+impl<K> Query<K> for Lookup<'_, K> {
+    fn key(&self) -> &K {
+        self.key
+    }
+
+    fn rank(&self) -> Rank {
+        self.rank
+    }
+}
+
+macro_rules! impl_erased_map {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident<K>: Any $(+ $bound:ident)*;
+    ) => {
+        $(#[$attr])*
+        $vis struct $name<K> {
+            inner: BTreeMap<Key<K>, SmallBox<dyn Any $(+ $bound)*>>,
+            #[cfg(debug_assertions)]
+            debug_type_names: BTreeMap<TypeId, &'static str>,
+        }
+
+        impl<K> $name<K> {
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`].")]
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let map = ", stringify!($name), "::<u32>::new();")]
+            /// ```
+            #[must_use]
+            pub fn new() -> Self {
+                Self {
+                    inner: BTreeMap::new(),
+                    #[cfg(debug_assertions)]
+                    debug_type_names: BTreeMap::new(),
+                }
+            }
+
+            /// Returns `true` if the map contains no values.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let map = ", stringify!($name), "::<u32>::new();")]
+            /// assert!(map.is_empty());
+            /// ```
+            #[must_use]
+            pub fn is_empty(&self) -> bool {
+                self.inner.is_empty()
+            }
+
+            /// Returns the number of values in the map, of all keys and types.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// map.insert(1, "a");
+            /// map.insert(1, 10_u32);
+            /// map.insert(2, "b");
+            /// assert_eq!(map.len(), 3);
+            /// ```
+            #[must_use]
+            pub fn len(&self) -> usize {
+                self.inner.len()
+            }
+
+            /// Clears the map, removing all values.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// map.insert(1, "a");
+            /// map.clear();
+            /// assert!(map.is_empty());
+            /// ```
+            pub fn clear(&mut self) {
+                self.inner.clear();
+
+                #[cfg(debug_assertions)]
+                self.debug_type_names.clear();
+            }
+        }
+
+        impl<K: Ord> $name<K> {
+            /// Returns `true` if the map contains an instance of `T` for `key`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// map.insert(1, "a");
+            /// assert!(map.contains::<&str>(&1));
+            /// assert!(!map.contains::<&str>(&2));
+            /// assert!(!map.contains::<u32>(&1));
+            /// ```
+            #[must_use]
+            pub fn contains<T>(&self, key: &K) -> bool
+            where
+                T: Any,
+            {
+                self.inner.contains_key(&Self::lookup::<T>(key) as &dyn Query<K>)
+            }
+
+            /// Returns a reference to the instance of `T` for `key`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// map.insert(1, "a");
+            /// assert_eq!(map.get::<&str>(&1), Some(&"a"));
+            /// assert_eq!(map.get::<&str>(&2), None);
+            /// ```
+            #[must_use]
+            pub fn get<T>(&self, key: &K) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.inner
+                    .get(&Self::lookup::<T>(key) as &dyn Query<K>)
+                    .map(|boxed_any| {
+                        // Sanity check
+                        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+                        let ptr = (boxed_any.as_ref() as *const dyn Any).cast::<T>();
+
+                        unsafe { &*ptr }
+                    })
+            }
+
+            /// Returns a mutable reference to the instance of `T` for `key`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// map.insert(1, String::from("a"));
+            /// map.get_mut::<String>(&1).unwrap().push('b');
+            /// assert_eq!(map.get::<String>(&1).map(String::as_str), Some("ab"));
+            /// ```
+            #[must_use]
+            pub fn get_mut<T>(&mut self, key: &K) -> Option<&mut T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.inner
+                    .get_mut(&Self::lookup::<T>(key) as &dyn Query<K>)
+                    .map(|boxed_any| {
+                        // Sanity check
+                        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+                        let ptr = (boxed_any.as_mut() as *mut dyn Any).cast::<T>();
+
+                        unsafe { &mut *ptr }
+                    })
+            }
+
+            /// Insert an instance of `T` for `key` into the map.
+            ///
+            /// If the map did not have this type present for `key`, [`None`] is returned.
+            ///
+            /// If the map did have this type present for `key`, the value is updated, and the
+            /// old value is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// assert_eq!(map.insert(1, "a"), None);
+            /// assert_eq!(map.insert(2, "b"), None);
+            /// assert_eq!(map.insert(1, "c"), Some("a"));
+            /// ```
+            pub fn insert<T>(&mut self, key: K, value: T) -> Option<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let vtable = crate::impl_erased_set! { @vtable T [$(+ $bound)*] [] };
+
+                #[cfg(debug_assertions)]
+                self.debug_type_names.insert(TypeId::of::<T>(), ::core::any::type_name::<T>());
+
+                let key = Key {
+                    key,
+                    type_id: TypeId::of::<T>(),
+                };
+
+                self.inner
+                    .insert(key, unsafe { SmallBox::new(value, vtable) })
+                    .map(|boxed_any| {
+                        // Sanity check
+                        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+                        unsafe { boxed_any.into_inner_unchecked() }
+                    })
+            }
+
+            /// Remove and return the instance of `T` for `key` from the map.
+            ///
+            /// If the map did not have this type present for `key`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// map.insert(1, "a");
+            /// assert_eq!(map.remove::<&str>(&1), Some("a"));
+            /// assert_eq!(map.remove::<&str>(&1), None);
+            /// ```
+            pub fn remove<T>(&mut self, key: &K) -> Option<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.inner
+                    .remove(&Self::lookup::<T>(key) as &dyn Query<K>)
+                    .map(|boxed_any| {
+                        // Sanity check
+                        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+                        unsafe { boxed_any.into_inner_unchecked() }
+                    })
+            }
+
+            /// Removes all the values for `key` from the map, returning how many were removed.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// map.insert(1, "a");
+            /// map.insert(1, 10_u32);
+            /// map.insert(2, "b");
+            /// assert_eq!(map.remove_key(&1), 2);
+            /// assert_eq!(map.len(), 1);
+            /// ```
+            pub fn remove_key(&mut self, key: &K) -> usize {
+                let first = Lookup {
+                    key,
+                    rank: Rank::First,
+                };
+                let last = Lookup {
+                    key,
+                    rank: Rank::Last,
+                };
+
+                let type_ids = self
+                    .inner
+                    .range::<dyn Query<K>, _>((
+                        Bound::Excluded(&first as &dyn Query<K>),
+                        Bound::Excluded(&last as &dyn Query<K>),
+                    ))
+                    .map(|(key, _)| key.type_id)
+                    .collect::<Vec<_>>();
+
+                for &type_id in &type_ids {
+                    let lookup = Lookup {
+                        key,
+                        rank: Rank::Type(type_id),
+                    };
+
+                    self.inner.remove(&lookup as &dyn Query<K>);
+                }
+
+                type_ids.len()
+            }
+
+            /// Gets an iterator over the keys with an instance of `T`, in ascending order.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// map.insert(2, "b");
+            /// map.insert(1, "a");
+            /// map.insert(3, 10_u32);
+            /// assert!(map.keys_with::<&str>().eq(&[1, 2]));
+            /// ```
+            pub fn keys_with<T>(&self) -> impl Iterator<Item = &K>
+            where
+                T: Any,
+            {
+                self.inner
+                    .keys()
+                    .filter(|key| key.type_id == TypeId::of::<T>())
+                    .map(|key| &key.key)
+            }
+
+            /// Gets an iterator over the instances of `T` and their keys, in ascending key order.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut map = ", stringify!($name), "::new();")]
+            /// map.insert(2, "b");
+            /// map.insert(1, "a");
+            /// map.insert(3, 10_u32);
+            /// assert!(map.values_of::<&str>().eq([(&1, &"a"), (&2, &"b")]));
+            /// ```
+            pub fn values_of<T>(&self) -> impl Iterator<Item = (&K, &T)>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.inner
+                    .iter()
+                    .filter(|(key, _)| key.type_id == TypeId::of::<T>())
+                    .map(|(key, boxed_any)| {
+                        // Sanity check
+                        debug_assert_eq!(Any::type_id(boxed_any.as_ref()), TypeId::of::<T>());
+
+                        let ptr = (boxed_any.as_ref() as *const dyn Any).cast::<T>();
+
+                        (&key.key, unsafe { &*ptr })
+                    })
+            }
+
+            fn lookup<T: Any>(key: &K) -> Lookup<'_, K> {
+                Lookup {
+                    key,
+                    rank: Rank::Type(TypeId::of::<T>()),
+                }
+            }
+        }
+
+        impl<K> ::core::default::Default for $name<K> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        /// Shows the key and type name of the values, or their [`TypeId`] in release mode.
+        impl<K: ::core::fmt::Debug> ::core::fmt::Debug for $name<K> {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                #[cfg(debug_assertions)]
+                let entries = self
+                    .inner
+                    .keys()
+                    .map(|key| (&key.key, self.debug_type_names[&key.type_id]));
+
+                #[cfg(not(debug_assertions))]
+                let entries = self.inner.keys().map(|key| (&key.key, key.type_id));
+
+                f.debug_map().entries(entries).finish()
+            }
+        }
+    };
+}
+
+impl_erased_map! {
+    /// A map from keys to erased sets, holding at most one instance of each type per key.
+    ///
+    /// Values are stored in a single map keyed by `(K, TypeId)`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # #[derive(Debug, PartialEq)]
+    /// # struct Position(i32, i32);
+    /// # #[derive(Debug, PartialEq)]
+    /// # struct Name(&'static str);
+    /// #
+    /// use erased_set::ErasedMap;
+    ///
+    /// let mut entities = ErasedMap::new();
+    /// entities.insert(1, Position(0, 0));
+    /// entities.insert(1, Name("player"));
+    /// entities.insert(2, Position(4, 2));
+    ///
+    /// assert_eq!(entities.get::<Name>(&1), Some(&Name("player")));
+    /// assert!(entities.keys_with::<Position>().eq(&[1, 2]));
+    ///
+    /// entities.remove_key(&1);
+    /// assert_eq!(entities.len(), 1);
+    /// ```
+    pub struct ErasedMap<K>: Any;
+}
+
+#[cfg(feature = "send")]
+impl_erased_map! {
+    /// Like [`ErasedMap`] but with a [`Send`] bound.
+    pub struct ErasedSendMap<K>: Any + Send;
+}
+
+#[cfg(feature = "sync")]
+impl_erased_map! {
+    /// Like [`ErasedMap`] but with a [`Send`] + [`Sync`] bound.
+    pub struct ErasedSyncMap<K>: Any + Send + Sync;
+}
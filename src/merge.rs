@@ -0,0 +1,27 @@
+use ::alloc::vec::Vec;
+use ::core::any::TypeId;
+
+/// How to resolve a type present in both sets of a merge.
+///
+/// This `enum` is passed to the `merge` method on the erased sets, see `merge_with` for a
+/// resolver callback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MergePolicy {
+    /// Keeps the value of the set merged into, the other value is dropped.
+    KeepExisting,
+    /// Replaces the value of the set merged into with the other value.
+    Overwrite,
+}
+
+/// The types affected by a merge.
+///
+/// This `struct` is returned by the `merge` and `merge_with` methods on the erased sets.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MergeReport {
+    /// The types that were only present in the other set.
+    pub added: Vec<TypeId>,
+    /// The types present in both sets whose value was replaced or resolved.
+    pub replaced: Vec<TypeId>,
+    /// The types present in both sets whose value was kept.
+    pub kept: Vec<TypeId>,
+}
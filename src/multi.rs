@@ -0,0 +1,485 @@
+use ::alloc::boxed::Box;
+use ::alloc::collections::{btree_map, BTreeMap};
+use ::core::any::{Any, TypeId};
+use ::core::iter::FusedIterator;
+
+/// The values of a single type `T` in an erased multi-set.
+struct Values<B: ?Sized> {
+    // A `Vec<T>`.
+    vec: Box<B>,
+    type_name: &'static str,
+    len: fn(&B) -> usize,
+    iter: for<'a> fn(&'a B) -> Box<dyn Iterator<Item = &'a B> + 'a>,
+    iter_mut: for<'a> fn(&'a mut B) -> Box<dyn Iterator<Item = &'a mut B> + 'a>,
+}
+
+impl<B: ?Sized> Values<B> {
+    fn len(&self) -> usize {
+        (self.len)(&self.vec)
+    }
+}
+
+/// An iterator over the values of an erased multi-set.
+///
+/// This `struct` is created by the `iter` method on the erased multi-sets.
+pub struct MultiIter<'a, B: ?Sized + 'a = dyn Any> {
+    types: btree_map::Iter<'a, TypeId, Values<B>>,
+    current: Option<(TypeId, Box<dyn Iterator<Item = &'a B> + 'a>)>,
+    remaining: usize,
+}
+
+impl<'a, B: ?Sized + 'a> MultiIter<'a, B> {
+    fn new(inner: &'a BTreeMap<TypeId, Values<B>>) -> Self {
+        Self {
+            types: inner.iter(),
+            current: None,
+            remaining: inner.values().map(Values::len).sum(),
+        }
+    }
+}
+
+impl<'a, B: ?Sized + 'a> Iterator for MultiIter<'a, B> {
+    type Item = (TypeId, &'a B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((type_id, values)) = &mut self.current {
+                if let Some(value) = values.next() {
+                    self.remaining -= 1;
+                    return Some((*type_id, value));
+                }
+            }
+
+            let (&type_id, values) = self.types.next()?;
+            self.current = Some((type_id, (values.iter)(&values.vec)));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, B: ?Sized + 'a> ExactSizeIterator for MultiIter<'a, B> {}
+
+impl<'a, B: ?Sized + 'a> FusedIterator for MultiIter<'a, B> {}
+
+/// A mutable iterator over the values of an erased multi-set.
+///
+/// This `struct` is created by the `iter_mut` method on the erased multi-sets.
+pub struct MultiIterMut<'a, B: ?Sized + 'a = dyn Any> {
+    types: btree_map::IterMut<'a, TypeId, Values<B>>,
+    current: Option<(TypeId, Box<dyn Iterator<Item = &'a mut B> + 'a>)>,
+    remaining: usize,
+}
+
+impl<'a, B: ?Sized + 'a> MultiIterMut<'a, B> {
+    fn new(inner: &'a mut BTreeMap<TypeId, Values<B>>) -> Self {
+        let remaining = inner.values().map(Values::len).sum();
+
+        Self {
+            types: inner.iter_mut(),
+            current: None,
+            remaining,
+        }
+    }
+}
+
+impl<'a, B: ?Sized + 'a> Iterator for MultiIterMut<'a, B> {
+    type Item = (TypeId, &'a mut B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((type_id, values)) = &mut self.current {
+                if let Some(value) = values.next() {
+                    self.remaining -= 1;
+                    return Some((*type_id, value));
+                }
+            }
+
+            let (&type_id, values) = self.types.next()?;
+            self.current = Some((type_id, (values.iter_mut)(&mut values.vec)));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, B: ?Sized + 'a> ExactSizeIterator for MultiIterMut<'a, B> {}
+
+impl<'a, B: ?Sized + 'a> FusedIterator for MultiIterMut<'a, B> {}
+
+macro_rules! impl_erased_multi_set {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $(+ $bound:ident)*;
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            inner: BTreeMap<TypeId, Values<dyn Any $(+ $bound)*>>,
+        }
+
+        impl $name {
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`].")]
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// ```
+            #[must_use]
+            pub fn new() -> Self {
+                Self {
+                    inner: BTreeMap::new(),
+                }
+            }
+
+            /// Returns `true` if the set contains no values.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert!(set.is_empty());
+            /// ```
+            #[must_use]
+            pub fn is_empty(&self) -> bool {
+                self.inner.is_empty()
+            }
+
+            /// Returns the number of values in the set, of all types.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push("a");
+            /// set.push("b");
+            /// set.push(1_u32);
+            /// assert_eq!(set.len(), 3);
+            /// ```
+            #[must_use]
+            pub fn len(&self) -> usize {
+                self.inner.values().map(Values::len).sum()
+            }
+
+            /// Clears the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push("a");
+            /// set.clear();
+            /// assert!(set.is_empty());
+            /// ```
+            pub fn clear(&mut self) {
+                self.inner.clear();
+            }
+
+            /// Returns `true` if the set contains at least one instance of `T`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push("a");
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u32>());
+            /// ```
+            #[must_use]
+            pub fn contains<T>(&self) -> bool
+            where
+                T: Any,
+            {
+                self.inner.contains_key(&TypeId::of::<T>())
+            }
+
+            /// Returns the number of instances of `T` in the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push("a");
+            /// set.push("b");
+            /// assert_eq!(set.count::<&str>(), 2);
+            /// assert_eq!(set.count::<u32>(), 0);
+            /// ```
+            #[must_use]
+            pub fn count<T>(&self) -> usize
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.get_all::<T>().len()
+            }
+
+            /// Appends an instance of `T` to the instances of `T` in the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push("a");
+            /// set.push("b");
+            /// assert_eq!(set.get_all::<&str>(), ["a", "b"]);
+            /// ```
+            #[allow(unused_parens)]
+            pub fn push<T>(&mut self, value: T)
+            where
+                T: Any $(+ $bound)*,
+            {
+                let values = self.inner.entry(TypeId::of::<T>()).or_insert_with(|| Values {
+                    vec: Box::new(::alloc::vec::Vec::<T>::new()),
+                    type_name: ::core::any::type_name::<T>(),
+                    len: |vec| Self::downcast_ref::<T>(vec).len(),
+                    iter: |vec| {
+                        Box::new(
+                            Self::downcast_ref::<T>(vec)
+                                .iter()
+                                .map(|value| value as &(dyn Any $(+ $bound)*)),
+                        )
+                    },
+                    iter_mut: |vec| {
+                        Box::new(
+                            Self::downcast_mut::<T>(vec)
+                                .iter_mut()
+                                .map(|value| value as &mut (dyn Any $(+ $bound)*)),
+                        )
+                    },
+                });
+
+                Self::downcast_mut::<T>(&mut *values.vec).push(value);
+            }
+
+            /// Returns the instances of `T` in the set, in insertion order.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push(1_u32);
+            /// set.push(2_u32);
+            /// assert_eq!(set.get_all::<u32>(), [1, 2]);
+            /// assert!(set.get_all::<&str>().is_empty());
+            /// ```
+            #[must_use]
+            pub fn get_all<T>(&self) -> &[T]
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.inner
+                    .get(&TypeId::of::<T>())
+                    .map_or(&[], |values| Self::downcast_ref::<T>(&*values.vec))
+            }
+
+            /// Returns the instances of `T` in the set, in insertion order.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push(1_u32);
+            /// set.push(2_u32);
+            /// for value in set.get_all_mut::<u32>() {
+            ///     *value *= 10;
+            /// }
+            /// assert_eq!(set.get_all::<u32>(), [10, 20]);
+            /// ```
+            #[must_use]
+            pub fn get_all_mut<T>(&mut self) -> &mut [T]
+            where
+                T: Any $(+ $bound)*,
+            {
+                match self.inner.get_mut(&TypeId::of::<T>()) {
+                    Some(values) => Self::downcast_mut::<T>(&mut *values.vec),
+                    None => &mut [],
+                }
+            }
+
+            /// Removes and returns the instances of `T` in the set, in insertion order.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push("a");
+            /// set.push("b");
+            /// assert_eq!(set.take_all::<&str>(), ["a", "b"]);
+            /// assert!(!set.contains::<&str>());
+            /// ```
+            pub fn take_all<T>(&mut self) -> ::alloc::vec::Vec<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.inner
+                    .remove(&TypeId::of::<T>())
+                    .map(|values| {
+                        // Sanity check
+                        debug_assert!(values.vec.is::<::alloc::vec::Vec<T>>());
+
+                        let ptr = Box::into_raw(values.vec).cast::<::alloc::vec::Vec<T>>();
+
+                        *unsafe { Box::from_raw(ptr) }
+                    })
+                    .unwrap_or_default()
+            }
+
+            /// Gets an iterator over the values and their [`TypeId`]s, grouped by type and in
+            /// insertion order within a type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push("a");
+            /// set.push("b");
+            /// set.push(1_u32);
+            /// assert_eq!(set.iter().count(), 3);
+            /// assert_eq!(set.iter().filter(|(_, value)| value.is::<&str>()).count(), 2);
+            /// ```
+            pub fn iter(&self) -> MultiIter<'_, dyn Any $(+ $bound)*> {
+                MultiIter::new(&self.inner)
+            }
+
+            /// Gets a mutable iterator over the values and their [`TypeId`]s, grouped by type
+            /// and in insertion order within a type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.push(1_u32);
+            /// set.push(2_u32);
+            /// for (_, value) in set.iter_mut() {
+            ///     if let Some(x) = value.downcast_mut::<u32>() {
+            ///         *x += 1;
+            ///     }
+            /// }
+            /// assert_eq!(set.get_all::<u32>(), [2, 3]);
+            /// ```
+            pub fn iter_mut(&mut self) -> MultiIterMut<'_, dyn Any $(+ $bound)*> {
+                MultiIterMut::new(&mut self.inner)
+            }
+
+            /// Gets an iterator over the [`TypeId`]s of stored elements, in arbitrary order.
+            pub fn type_ids(&self) -> impl Iterator<Item = &TypeId> {
+                self.inner.keys()
+            }
+
+            #[allow(unused_parens)]
+            fn downcast_ref<T: Any>(vec: &(dyn Any $(+ $bound)*)) -> &::alloc::vec::Vec<T> {
+                // Sanity check
+                debug_assert!(vec.is::<::alloc::vec::Vec<T>>());
+
+                unsafe { &*(vec as *const dyn Any).cast() }
+            }
+
+            #[allow(unused_parens)]
+            fn downcast_mut<T: Any>(
+                vec: &mut (dyn Any $(+ $bound)*),
+            ) -> &mut ::alloc::vec::Vec<T> {
+                // Sanity check
+                debug_assert!(vec.is::<::alloc::vec::Vec<T>>());
+
+                unsafe { &mut *(vec as *mut dyn Any).cast() }
+            }
+        }
+
+        /// Shows the number of values of each type.
+        impl ::core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.debug_map()
+                    .entries(self.inner.values().map(|values| (values.type_name, values.len())))
+                    .finish()
+            }
+        }
+
+        impl<'a> ::core::iter::IntoIterator for &'a $name {
+            type Item = <Self::IntoIter as ::core::iter::Iterator>::Item;
+            type IntoIter = MultiIter<'a, dyn Any $(+ $bound)*>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl<'a> ::core::iter::IntoIterator for &'a mut $name {
+            type Item = <Self::IntoIter as ::core::iter::Iterator>::Item;
+            type IntoIter = MultiIterMut<'a, dyn Any $(+ $bound)*>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter_mut()
+            }
+        }
+    };
+}
+
+impl_erased_multi_set! {
+    /// A collection of erased types, holding any number of instances of each type.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # #[derive(Debug, PartialEq)]
+    /// # struct ClickEvent(u32, u32);
+    /// # #[derive(Debug, PartialEq)]
+    /// # struct KeyDownEvent(char);
+    /// #
+    /// use erased_set::ErasedMultiSet;
+    ///
+    /// let mut events = ErasedMultiSet::new();
+    /// events.push(ClickEvent(128, 256));
+    /// events.push(KeyDownEvent('z'));
+    /// events.push(ClickEvent(0, 0));
+    ///
+    /// assert_eq!(events.count::<ClickEvent>(), 2);
+    /// assert_eq!(events.get_all::<KeyDownEvent>(), [KeyDownEvent('z')]);
+    ///
+    /// let clicks = events.take_all::<ClickEvent>();
+    /// assert_eq!(clicks, [ClickEvent(128, 256), ClickEvent(0, 0)]);
+    /// assert_eq!(events.len(), 1);
+    /// ```
+    #[derive(Default)]
+    pub struct ErasedMultiSet: Any;
+}
+
+#[cfg(feature = "send")]
+impl_erased_multi_set! {
+    /// Like [`ErasedMultiSet`] but with a [`Send`] bound.
+    #[derive(Default)]
+    pub struct ErasedSendMultiSet: Any + Send;
+}
+
+#[cfg(feature = "sync")]
+impl_erased_multi_set! {
+    /// Like [`ErasedMultiSet`] but with a [`Send`] + [`Sync`] bound.
+    #[derive(Default)]
+    pub struct ErasedSyncMultiSet: Any + Send + Sync;
+}
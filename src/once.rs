@@ -0,0 +1,350 @@
+use ::alloc::collections::{btree_map, BTreeMap};
+use ::alloc::rc::Rc;
+#[cfg(feature = "std")]
+use ::alloc::sync::Arc;
+use ::core::any::{Any, TypeId};
+use ::core::cell::RefCell;
+use ::core::fmt;
+#[cfg(feature = "std")]
+use ::std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(feature = "std")]
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(feature = "std")]
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(feature = "std")]
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Values are never removed or replaced while the set is borrowed, references to them are
+// therefore valid for the lifetime of the borrow. They are kept in an `Rc` or an `Arc` rather
+// than a `Box` so that moving them within the map does not invalidate these references.
+macro_rules! impl_erased_once_set {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $(+ $bound:ident)*;
+
+        $cell:ident, $read:path, $write:path, $ptr:ident
+        $(, $field:ident: $field_ty:ty = $field_init:expr)*
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            // `BTreeMap::new` is not `const` in the MSRV.
+            inner: $cell<Option<BTreeMap<TypeId, $ptr<dyn Any $(+ $bound)*>>>>,
+            $($field: $field_ty,)*
+        }
+
+        impl $name {
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`].")]
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// ```
+            #[must_use]
+            pub const fn new() -> Self {
+                Self {
+                    inner: $cell::new(None),
+                    $($field: $field_init,)*
+                }
+            }
+
+            /// Returns `true` if the set contains no instances of any type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert!(set.is_empty());
+            /// set.get_or_init(|| 1_u8);
+            /// assert!(!set.is_empty());
+            /// ```
+            #[must_use]
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            /// Returns the number of types in the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// set.get_or_init(|| 1_u8);
+            /// set.get_or_init(|| "a");
+            /// assert_eq!(set.len(), 2);
+            /// ```
+            #[must_use]
+            pub fn len(&self) -> usize {
+                $read(&self.inner).as_ref().map_or(0, BTreeMap::len)
+            }
+
+            /// Returns `true` if the set contains an instance of `T`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// set.get_or_init(|| "a");
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u8>());
+            /// ```
+            #[must_use]
+            pub fn contains<T>(&self) -> bool
+            where
+                T: Any,
+            {
+                $read(&self.inner)
+                    .as_ref()
+                    .map_or(false, |inner| inner.contains_key(&TypeId::of::<T>()))
+            }
+
+            /// Returns a reference to the instance of type `T`, if it was initialized.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.get::<&str>(), None);
+            /// set.get_or_init(|| "a");
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// ```
+            #[must_use]
+            pub fn get<T>(&self) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let inner = $read(&self.inner);
+                let value = inner.as_ref()?.get(&TypeId::of::<T>())?;
+
+                // Sanity check
+                debug_assert_eq!(Any::type_id(&**value), TypeId::of::<T>());
+
+                let ptr = ($ptr::as_ptr(value) as *const dyn Any).cast::<T>();
+
+                Some(unsafe { &*ptr })
+            }
+
+            /// Returns a reference to the instance of type `T`, initializing it with `f` if the
+            /// set did not have this type present.
+            ///
+            /// The set is not borrowed while `f` runs, `f` may therefore initialize other types
+            /// of the set. If an instance of `T` is stored meanwhile, it is kept and the value
+            /// returned by `f` is dropped.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            /// # #[derive(Debug, PartialEq)]
+            /// struct Config(&'static str);
+            /// # #[derive(Debug, PartialEq)]
+            /// struct Client(&'static str);
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// let client = set.get_or_init(|| {
+            ///     let config = set.get_or_init(|| Config("localhost"));
+            ///     Client(config.0)
+            /// });
+            ///
+            /// assert_eq!(client, &Client("localhost"));
+            /// assert_eq!(set.get_or_init(|| Client("other")), &Client("localhost"));
+            /// ```
+            pub fn get_or_init<T>(&self, f: impl FnOnce() -> T) -> &T
+            where
+                T: Any $(+ $bound)*,
+            {
+                if let Some(value) = self.get::<T>() {
+                    return value;
+                }
+
+                self.init(f)
+            }
+
+            /// Initializes the instance of type `T` with `value`.
+            ///
+            /// Returns `Err(value)` if the set already had this type present, the stored value is
+            /// never replaced.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.set("a"), Ok(()));
+            /// assert_eq!(set.set("b"), Err("b"));
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// ```
+            pub fn set<T>(&self, value: T) -> Result<(), T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let mut inner = $write(&self.inner);
+
+                match inner
+                    .get_or_insert_with(BTreeMap::new)
+                    .entry(TypeId::of::<T>())
+                {
+                    btree_map::Entry::Occupied(_) => Err(value),
+                    btree_map::Entry::Vacant(entry) => {
+                        entry.insert($ptr::new(value));
+                        Ok(())
+                    }
+                }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    .field("len", &self.len())
+                    .finish_non_exhaustive()
+            }
+        }
+    };
+}
+
+impl_erased_once_set! {
+    /// A set of erased types, each initialized at most once and never replaced.
+    ///
+    /// Like a [`OnceCell`](::core::cell::OnceCell) per type, initialization only needs `&self`
+    /// and references to the values are valid for the lifetime of the set. See
+    /// `ErasedSyncOnceSet` for a thread-safe variant.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedOnceSet;
+    ///
+    /// # #[derive(Debug, PartialEq)]
+    /// struct Config(&'static str);
+    ///
+    /// let services = ErasedOnceSet::new();
+    /// let config = services.get_or_init(|| Config("localhost"));
+    ///
+    /// // `config` is still valid after initializing other types.
+    /// services.get_or_init(|| 443_u16);
+    /// assert_eq!(config, &Config("localhost"));
+    /// ```
+    pub struct ErasedOnceSet: Any;
+
+    RefCell, RefCell::borrow, RefCell::borrow_mut, Rc
+}
+
+impl ErasedOnceSet {
+    fn init<T: Any>(&self, f: impl FnOnce() -> T) -> &T {
+        let _ = self.set(f());
+
+        self.get::<T>()
+            .expect("the value was stored by `set` and cannot be removed")
+    }
+}
+
+#[cfg(feature = "std")]
+impl_erased_once_set! {
+    /// Like [`ErasedOnceSet`] but with a [`Send`] + [`Sync`] bound, for use from multiple
+    /// threads, e.g. in a `static`.
+    ///
+    /// Like a [`OnceLock`](::std::sync::OnceLock) per type, threads initializing a type
+    /// concurrently wait for the first one, whose initialization function is the only one to run
+    /// unless it panics. As with a `OnceLock`, initializing a type from its own initialization
+    /// function deadlocks.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedSyncOnceSet;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// # #[derive(Debug, PartialEq)]
+    /// struct Config(&'static str);
+    ///
+    /// static SERVICES: ErasedSyncOnceSet = ErasedSyncOnceSet::new();
+    /// static INITS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let threads: Vec<_> = (0..4)
+    ///     .map(|_| {
+    ///         std::thread::spawn(|| {
+    ///             SERVICES.get_or_init(|| {
+    ///                 INITS.fetch_add(1, Ordering::Relaxed);
+    ///                 Config("localhost")
+    ///             })
+    ///         })
+    ///     })
+    ///     .collect();
+    ///
+    /// for thread in threads {
+    ///     thread.join().unwrap();
+    /// }
+    ///
+    /// assert_eq!(SERVICES.get::<Config>(), Some(&Config("localhost")));
+    /// assert_eq!(INITS.load(Ordering::Relaxed), 1);
+    /// ```
+    pub struct ErasedSyncOnceSet: Any + Send + Sync;
+
+    RwLock, read, write, Arc,
+    initializing: Mutex<Option<BTreeMap<TypeId, Arc<Mutex<()>>>>> = Mutex::new(None)
+}
+
+#[cfg(feature = "std")]
+impl ErasedSyncOnceSet {
+    fn init<T: Any + Send + Sync>(&self, f: impl FnOnce() -> T) -> &T {
+        let type_id = TypeId::of::<T>();
+
+        // The callers initializing `T` concurrently share this lock, only the first one to take it
+        // runs its function, unless it panics.
+        let init_lock = Arc::clone(
+            lock(&self.initializing)
+                .get_or_insert_with(BTreeMap::new)
+                .entry(type_id)
+                .or_default(),
+        );
+
+        {
+            let _guard = lock(&init_lock);
+
+            if self.get::<T>().is_none() {
+                let _ = self.set(f());
+            }
+        }
+
+        // Later callers find the value, the lock is no longer needed.
+        if let Some(initializing) = lock(&self.initializing).as_mut() {
+            if initializing
+                .get(&type_id)
+                .map_or(false, |other| Arc::ptr_eq(other, &init_lock))
+            {
+                initializing.remove(&type_id);
+            }
+        }
+
+        self.get::<T>()
+            .expect("the value was stored by `set` and cannot be removed")
+    }
+}
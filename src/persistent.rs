@@ -0,0 +1,449 @@
+use ::alloc::sync::Arc;
+use ::alloc::vec::Vec;
+use ::core::any::{Any, TypeId};
+use ::core::fmt;
+use ::core::hash::{Hash, Hasher};
+use ::core::ops::Deref;
+
+use crate::storage::TypeIdHasher;
+
+/// The number of buckets of a [`PersistentMap`].
+const WIDTH: usize = 16;
+
+type Bucket<V> = Vec<(TypeId, V)>;
+
+/// A map from [`TypeId`] to value whose clones share their storage.
+///
+/// The map is a two-level trie: a root of [`WIDTH`] buckets, each a sorted vector. Both levels are
+/// reference counted and copied on write, so that a mutation only copies the root and the bucket
+/// it touches.
+#[derive(Clone)]
+struct PersistentMap<V> {
+    root: Arc<[Option<Arc<Bucket<V>>>; WIDTH]>,
+    len: usize,
+}
+
+impl<V: Clone> PersistentMap<V> {
+    fn new() -> Self {
+        Self {
+            root: Arc::new(::core::array::from_fn(|_| None)),
+            len: 0,
+        }
+    }
+
+    fn bucket(type_id: &TypeId) -> usize {
+        let mut hasher = TypeIdHasher::default();
+        type_id.hash(&mut hasher);
+
+        (hasher.finish() % WIDTH as u64) as usize
+    }
+
+    fn search(bucket: &Bucket<V>, type_id: &TypeId) -> Result<usize, usize> {
+        bucket.binary_search_by(|(key, _)| key.cmp(type_id))
+    }
+
+    fn get(&self, type_id: &TypeId) -> Option<&V> {
+        let bucket = self.root[Self::bucket(type_id)].as_deref()?;
+        let index = Self::search(bucket, type_id).ok()?;
+
+        Some(&bucket[index].1)
+    }
+
+    fn get_mut(&mut self, type_id: &TypeId) -> Option<&mut V> {
+        // Avoid copying on a miss.
+        self.get(type_id)?;
+
+        let bucket = Arc::make_mut(&mut self.root)[Self::bucket(type_id)].as_mut()?;
+        let bucket = Arc::make_mut(bucket);
+        let index = Self::search(bucket, type_id).ok()?;
+
+        Some(&mut bucket[index].1)
+    }
+
+    fn insert(&mut self, type_id: TypeId, value: V) -> Option<V> {
+        let bucket = Arc::make_mut(&mut self.root)[Self::bucket(&type_id)]
+            .get_or_insert_with(|| Arc::new(Vec::new()));
+        let bucket = Arc::make_mut(bucket);
+
+        match Self::search(bucket, &type_id) {
+            Ok(index) => Some(::core::mem::replace(&mut bucket[index].1, value)),
+            Err(index) => {
+                bucket.insert(index, (type_id, value));
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    fn remove(&mut self, type_id: &TypeId) -> Option<V> {
+        // Avoid copying on a miss.
+        self.get(type_id)?;
+
+        let slot = &mut Arc::make_mut(&mut self.root)[Self::bucket(type_id)];
+        let bucket = Arc::make_mut(slot.as_mut()?);
+        let index = Self::search(bucket, type_id).ok()?;
+        let (_, value) = bucket.remove(index);
+
+        if bucket.is_empty() {
+            *slot = None;
+        }
+
+        self.len -= 1;
+
+        Some(value)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &(TypeId, V)> {
+        self.root.iter().flatten().flat_map(|bucket| bucket.iter())
+    }
+}
+
+/// A value of a persistent set.
+struct Entry<B: ?Sized> {
+    value: Arc<B>,
+    type_name: &'static str,
+}
+
+impl<B: ?Sized> Clone for Entry<B> {
+    fn clone(&self) -> Self {
+        Self {
+            value: Arc::clone(&self.value),
+            type_name: self.type_name,
+        }
+    }
+}
+
+/// An immutable view of a persistent set.
+///
+/// This `struct` is created by the `snapshot` method on the persistent sets, it dereferences to
+/// the set and thereby only exposes its `&self` methods.
+#[derive(Clone)]
+pub struct Snapshot<S> {
+    set: S,
+}
+
+impl<S> Deref for Snapshot<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.set
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Snapshot<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.set.fmt(f)
+    }
+}
+
+macro_rules! impl_erased_persistent_set {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $(+ $bound:ident)*;
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone)]
+        $vis struct $name {
+            inner: PersistentMap<Entry<dyn Any $(+ $bound)*>>,
+        }
+
+        impl $name {
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`].")]
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// ```
+            #[must_use]
+            pub fn new() -> Self {
+                Self {
+                    inner: PersistentMap::new(),
+                }
+            }
+
+            /// Returns `true` if the set contains no instances of any type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert!(set.is_empty());
+            /// ```
+            #[must_use]
+            pub fn is_empty(&self) -> bool {
+                self.inner.len == 0
+            }
+
+            /// Returns the number of types in the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.insert("a");
+            /// assert_eq!(set.len(), 2);
+            /// ```
+            #[must_use]
+            pub fn len(&self) -> usize {
+                self.inner.len
+            }
+
+            /// Clears the set, other sets sharing its storage are left untouched.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.clear();
+            /// assert!(set.is_empty());
+            /// ```
+            pub fn clear(&mut self) {
+                self.inner = PersistentMap::new();
+            }
+
+            /// Returns `true` if the set contains an instance of `T`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u8>());
+            /// ```
+            #[must_use]
+            pub fn contains<T>(&self) -> bool
+            where
+                T: Any,
+            {
+                self.inner.get(&TypeId::of::<T>()).is_some()
+            }
+
+            /// Returns a reference to an instance of `T`.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// assert_eq!(set.get::<u8>(), None);
+            /// ```
+            #[must_use]
+            pub fn get<T>(&self) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.inner.get(&TypeId::of::<T>()).map(|entry| {
+                    // Sanity check
+                    debug_assert_eq!(Any::type_id(&*entry.value), TypeId::of::<T>());
+
+                    let ptr = (Arc::as_ptr(&entry.value) as *const dyn Any).cast::<T>();
+
+                    unsafe { &*ptr }
+                })
+            }
+
+            /// Returns a mutable reference to an instance of `T`.
+            ///
+            /// If the value is shared with another set, it is cloned first, the other values
+            /// stay shared.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut parent = ", stringify!($name), "::new();")]
+            /// parent.insert(String::from("a"));
+            /// parent.insert(1_u8);
+            ///
+            /// let mut child = parent.clone();
+            /// child.get_mut::<String>().unwrap().push('b');
+            ///
+            /// assert_eq!(parent.get::<String>().map(String::as_str), Some("a"));
+            /// assert_eq!(child.get::<String>().map(String::as_str), Some("ab"));
+            /// assert!(std::ptr::eq(parent.get::<u8>().unwrap(), child.get::<u8>().unwrap()));
+            /// ```
+            #[must_use]
+            pub fn get_mut<T>(&mut self) -> Option<&mut T>
+            where
+                T: Any + Clone $(+ $bound)*,
+            {
+                let value = &mut self.inner.get_mut(&TypeId::of::<T>())?.value;
+
+                // Sanity check
+                debug_assert_eq!(Any::type_id(&**value), TypeId::of::<T>());
+
+                if Arc::get_mut(value).is_none() {
+                    let ptr = (Arc::as_ptr(value) as *const dyn Any).cast::<T>();
+
+                    *value = Arc::new(T::clone(unsafe { &*ptr }));
+                }
+
+                let value = Arc::get_mut(value).expect("the value was just made unique");
+                let ptr = (value as *mut dyn Any).cast::<T>();
+
+                Some(unsafe { &mut *ptr })
+            }
+
+            /// Insert an instance of type `T` into the set.
+            ///
+            /// Returns the replaced value if any, cloned if it is shared with another set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.insert("a"), None);
+            /// assert_eq!(set.insert("b"), Some("a"));
+            /// ```
+            pub fn insert<T>(&mut self, value: T) -> Option<T>
+            where
+                T: Any + Clone $(+ $bound)*,
+            {
+                let entry = Entry {
+                    value: Arc::new(value) as Arc<dyn Any $(+ $bound)*>,
+                    type_name: ::core::any::type_name::<T>(),
+                };
+
+                self.inner
+                    .insert(TypeId::of::<T>(), entry)
+                    .map(|entry| Self::unwrap(entry.value))
+            }
+
+            /// Remove and return an instance of type `T` from the set.
+            ///
+            /// Returns the value if any, cloned if it is shared with another set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            ///
+            /// let snapshot = set.snapshot();
+            /// assert_eq!(set.remove::<&str>(), Some("a"));
+            /// assert_eq!(set.remove::<&str>(), None);
+            /// assert_eq!(snapshot.get::<&str>(), Some(&"a"));
+            /// ```
+            pub fn remove<T>(&mut self) -> Option<T>
+            where
+                T: Any + Clone $(+ $bound)*,
+            {
+                self.inner
+                    .remove(&TypeId::of::<T>())
+                    .map(|entry| Self::unwrap(entry.value))
+            }
+
+            /// Returns an immutable view of the set in its current state, in O(1).
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            ///
+            /// let snapshot = set.snapshot();
+            /// set.insert(2_u8);
+            ///
+            /// assert_eq!(snapshot.get::<u8>(), Some(&1));
+            /// assert_eq!(set.get::<u8>(), Some(&2));
+            /// ```
+            #[must_use]
+            pub fn snapshot(&self) -> Snapshot<Self> {
+                Snapshot { set: self.clone() }
+            }
+
+            /// Gets an iterator over the [`TypeId`]s of stored elements, in arbitrary order.
+            pub fn type_ids(&self) -> impl Iterator<Item = &TypeId> {
+                self.inner.iter().map(|(type_id, _)| type_id)
+            }
+
+            fn unwrap<T: Any + Clone>(value: Arc<dyn Any $(+ $bound)*>) -> T {
+                // Sanity check
+                debug_assert_eq!(Any::type_id(&*value), TypeId::of::<T>());
+
+                let ptr = (Arc::into_raw(value) as *const dyn Any).cast::<T>();
+
+                Arc::try_unwrap(unsafe { Arc::from_raw(ptr) }).unwrap_or_else(|arc| T::clone(&arc))
+            }
+        }
+
+        impl ::core::default::Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        /// Shows the type names of the values.
+        impl ::core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.debug_set()
+                    .entries(self.inner.iter().map(|(_, entry)| entry.type_name))
+                    .finish()
+            }
+        }
+    };
+}
+
+impl_erased_persistent_set! {
+    /// A persistent set of erased types, whose clones share their storage.
+    ///
+    /// Cloning the set is O(1). Values are reference counted and copied on write: a mutation only
+    /// copies the value it touches, along with a fixed-size part of the map of the values. The
+    /// types must implement [`Clone`] to be copied on write.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedPersistentSet;
+    ///
+    /// # #[derive(Clone, Debug, PartialEq)]
+    /// struct User(&'static str);
+    /// # #[derive(Clone, Debug, PartialEq)]
+    /// struct Path(&'static str);
+    ///
+    /// let mut context = ErasedPersistentSet::new();
+    /// context.insert(Path("/"));
+    ///
+    /// // Fork the context for a middleware layer.
+    /// let mut layer = context.clone();
+    /// layer.insert(User("alice"));
+    ///
+    /// assert_eq!(layer.get::<Path>(), Some(&Path("/")));
+    /// assert!(!context.contains::<User>());
+    /// ```
+    pub struct ErasedPersistentSet: Any;
+}
+
+#[cfg(feature = "sync")]
+impl_erased_persistent_set! {
+    /// Like [`ErasedPersistentSet`] but with a [`Send`] + [`Sync`] bound.
+    pub struct ErasedSyncPersistentSet: Any + Send + Sync;
+}
@@ -0,0 +1,418 @@
+//! Serialization of the erased sets through a registry of types.
+//!
+//! The concrete types of the values of an erased set are unknown to `serde`, so they are
+//! registered upfront in a [`TypeRegistry`], each under a stable name. The registry serializes a
+//! set as a map from name to value, and deserializes such a map back into a set, inserting each
+//! value with its concrete type.
+//!
+//! # Example
+//!
+//! ```
+//! use erased_set::registry::TypeRegistry;
+//! use erased_set::ErasedSet;
+//!
+//! let mut registry = TypeRegistry::new();
+//! registry.register::<u32>("visits");
+//! registry.register::<String>("user");
+//!
+//! let mut set = ErasedSet::new();
+//! set.insert(3_u32);
+//! set.insert(String::from("ferris"));
+//!
+//! let json = serde_json::to_string(&registry.serialize_set(&set)).unwrap();
+//! assert_eq!(json, r#"{"user":"ferris","visits":3}"#);
+//!
+//! let mut deserializer = serde_json::Deserializer::from_str(&json);
+//! let set: ErasedSet = registry.deserialize_set(&mut deserializer).unwrap();
+//! assert_eq!(set.get::<u32>(), Some(&3));
+//! assert_eq!(set.get::<String>().map(String::as_str), Some("ferris"));
+//! ```
+
+use ::alloc::collections::BTreeMap;
+use ::alloc::string::String;
+use ::alloc::vec::Vec;
+use ::core::any::{Any, TypeId};
+use ::core::fmt;
+use ::core::marker::PhantomData;
+use ::serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, Visitor};
+use ::serde::ser::{self, Serialize, SerializeMap, Serializer};
+
+use crate::__private::{Stage, Transactional};
+use crate::ErasedSet;
+
+/// The erased type of the values of a set, e.g. `dyn Any + Send`.
+pub trait AsAny: 'static {
+    /// Returns the value as a `dyn Any`.
+    fn as_any(&self) -> &dyn Any;
+}
+
+macro_rules! impl_erase {
+    ($($bound:ident)*) => {
+        impl AsAny for dyn Any $(+ $bound)* {
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
+impl_erase!();
+impl_erase!(Send);
+impl_erase!(Send Sync);
+
+/// Returns the value as a `dyn erased_serde::Serialize` if it is a `T`.
+type SerializeFn = fn(&dyn Any) -> Option<&dyn erased_serde::Serialize>;
+
+/// Deserializes a `T` and inserts it into the set.
+type DeserializeFn<S> =
+    fn(&mut dyn erased_serde::Deserializer<'_>, &mut S) -> Result<(), erased_serde::Error>;
+
+struct Registration<S> {
+    name: &'static str,
+    serialize: SerializeFn,
+    deserialize: DeserializeFn<S>,
+}
+
+fn serialize<T: Serialize + Any>(value: &dyn Any) -> Option<&dyn erased_serde::Serialize> {
+    value
+        .downcast_ref::<T>()
+        .map(|value| value as &dyn erased_serde::Serialize)
+}
+
+fn deserialize<T, S>(
+    deserializer: &mut dyn erased_serde::Deserializer<'_>,
+    set: &mut S,
+) -> Result<(), erased_serde::Error>
+where
+    T: DeserializeOwned,
+    S: Stage<T>,
+{
+    set.stage(erased_serde::deserialize::<T>(deserializer)?);
+    Ok(())
+}
+
+/// A registry of the types that can be serialized in an erased set of type `S`, each under a
+/// stable name.
+///
+/// `S` is any set generated by [`impl_erased_set!`](crate::impl_erased_set), e.g. an
+/// [`ErasedSet`] or an [`ErasedCloneSet`](crate::ErasedCloneSet). Deserialized values are
+/// inserted with their concrete type, like [`insert`](ErasedSet::insert) does.
+///
+/// By default, serializing a set that holds an unregistered type and deserializing an unknown
+/// name both fail, see [`TypeRegistry::skip_unregistered_types`] and
+/// [`TypeRegistry::skip_unknown_names`].
+pub struct TypeRegistry<S = ErasedSet> {
+    registrations: BTreeMap<TypeId, Registration<S>>,
+    type_ids: BTreeMap<&'static str, TypeId>,
+    skip_unregistered_types: bool,
+    skip_unknown_names: bool,
+}
+
+impl<S> TypeRegistry<S> {
+    /// Creates an empty `TypeRegistry`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            registrations: BTreeMap::new(),
+            type_ids: BTreeMap::new(),
+            skip_unregistered_types: false,
+            skip_unknown_names: false,
+        }
+    }
+
+    /// Registers `T` under `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` or `name` is already registered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::registry::TypeRegistry;
+    /// use erased_set::ErasedCloneSet;
+    ///
+    /// let mut registry = TypeRegistry::<ErasedCloneSet>::new();
+    /// registry.register::<u32>("visits").register::<String>("user");
+    /// assert!(registry.is_registered::<u32>());
+    /// ```
+    pub fn register<T>(&mut self, name: &'static str) -> &mut Self
+    where
+        T: Any + Serialize + DeserializeOwned,
+        S: Stage<T>,
+    {
+        let type_id = TypeId::of::<T>();
+
+        assert!(
+            !self.registrations.contains_key(&type_id),
+            "type `{}` is already registered",
+            ::core::any::type_name::<T>()
+        );
+        assert!(
+            !self.type_ids.contains_key(name),
+            "name `{name}` is already registered"
+        );
+
+        self.registrations.insert(
+            type_id,
+            Registration {
+                name,
+                serialize: serialize::<T>,
+                deserialize: deserialize::<T, S>,
+            },
+        );
+        self.type_ids.insert(name, type_id);
+
+        self
+    }
+
+    /// Returns `true` if `T` is registered.
+    #[must_use]
+    pub fn is_registered<T: Any>(&self) -> bool {
+        self.registrations.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Returns the name `T` is registered under.
+    #[must_use]
+    pub fn name_of<T: Any>(&self) -> Option<&'static str> {
+        self.registrations
+            .get(&TypeId::of::<T>())
+            .map(|registration| registration.name)
+    }
+
+    /// Skips the values of unregistered types when serializing, instead of failing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::registry::TypeRegistry;
+    /// use erased_set::ErasedSet;
+    ///
+    /// let mut registry = TypeRegistry::new();
+    /// registry.register::<u32>("visits");
+    ///
+    /// let mut set = ErasedSet::new();
+    /// set.insert(3_u32);
+    /// set.insert(1.5_f64);
+    /// assert!(serde_json::to_string(&registry.serialize_set(&set)).is_err());
+    ///
+    /// registry.skip_unregistered_types(true);
+    /// let json = serde_json::to_string(&registry.serialize_set(&set)).unwrap();
+    /// assert_eq!(json, r#"{"visits":3}"#);
+    /// ```
+    pub fn skip_unregistered_types(&mut self, skip: bool) -> &mut Self {
+        self.skip_unregistered_types = skip;
+        self
+    }
+
+    /// Skips the values of unknown names when deserializing, instead of failing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::registry::TypeRegistry;
+    /// use erased_set::ErasedSet;
+    ///
+    /// let mut registry = TypeRegistry::new();
+    /// registry.register::<u32>("visits");
+    ///
+    /// let json = r#"{"visits":3,"theme":"dark"}"#;
+    /// let result: Result<ErasedSet, _> =
+    ///     registry.deserialize_set(&mut serde_json::Deserializer::from_str(json));
+    /// assert!(result.unwrap_err().to_string().contains("unknown type name `theme`"));
+    ///
+    /// registry.skip_unknown_names(true);
+    /// let set: ErasedSet = registry
+    ///     .deserialize_set(&mut serde_json::Deserializer::from_str(json))
+    ///     .unwrap();
+    /// assert_eq!(set.len(), 1);
+    /// ```
+    pub fn skip_unknown_names(&mut self, skip: bool) -> &mut Self {
+        self.skip_unknown_names = skip;
+        self
+    }
+
+    /// Returns a [`Serialize`] implementation for `set`, a map from name to value.
+    ///
+    /// The entries are sorted by name. The error on an unregistered type names it in debug
+    /// builds only, release builds do not keep the type names.
+    pub fn serialize_set<'a>(&'a self, set: &'a S) -> SerializeSet<'a, S> {
+        SerializeSet {
+            registry: self,
+            set,
+        }
+    }
+
+    /// Returns a [`DeserializeSeed`] implementation for a set of type `S`.
+    ///
+    /// A name that appears more than once replaces the previous value.
+    #[must_use]
+    pub fn deserialize_seed(&self) -> DeserializeSet<'_, S> {
+        DeserializeSet {
+            registry: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Deserializes a set of type `S`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the input is not a map from name to value, or contains an unknown name and
+    /// unknown names are not skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::registry::TypeRegistry;
+    /// use erased_set::ErasedCloneSet;
+    ///
+    /// let mut registry = TypeRegistry::<ErasedCloneSet>::new();
+    /// registry.register::<u32>("visits");
+    ///
+    /// let json = r#"{"visits":3}"#;
+    /// let set = registry
+    ///     .deserialize_set(&mut serde_json::Deserializer::from_str(json))
+    ///     .unwrap();
+    /// assert_eq!(set.clone().get::<u32>(), Some(&3));
+    /// ```
+    pub fn deserialize_set<'de, D>(&self, deserializer: D) -> Result<S, D::Error>
+    where
+        S: Transactional,
+        D: Deserializer<'de>,
+    {
+        self.deserialize_seed().deserialize(deserializer)
+    }
+}
+
+impl<S> Default for TypeRegistry<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> fmt::Debug for TypeRegistry<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypeRegistry")
+            .field("names", &self.type_ids.keys())
+            .field("skip_unregistered_types", &self.skip_unregistered_types)
+            .field("skip_unknown_names", &self.skip_unknown_names)
+            .finish()
+    }
+}
+
+/// Serializes an erased set as a map from name to value.
+///
+/// This `struct` is created by [`TypeRegistry::serialize_set`].
+pub struct SerializeSet<'a, S> {
+    registry: &'a TypeRegistry<S>,
+    set: &'a S,
+}
+
+impl<'a, B, S> Serialize for SerializeSet<'a, S>
+where
+    B: ?Sized + AsAny + 'a,
+    S: Transactional,
+    &'a S: IntoIterator<Item = (TypeId, &'a B)>,
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let mut entries = Vec::new();
+
+        for (type_id, value) in self.set {
+            let Some(registration) = self.registry.registrations.get(&type_id) else {
+                if self.registry.skip_unregistered_types {
+                    continue;
+                }
+
+                return Err(match self.set.type_name(&type_id) {
+                    Some(name) => {
+                        ser::Error::custom(format_args!("type `{name}` is not registered"))
+                    }
+                    None => ser::Error::custom(format_args!(
+                        "type {type_id:?} is not registered, its name is only known in debug builds"
+                    )),
+                });
+            };
+
+            let value = (registration.serialize)(value.as_any())
+                .ok_or_else(|| ser::Error::custom("value does not match its type id"))?;
+
+            entries.push((registration.name, value));
+        }
+
+        // The output does not depend on the order of the storage.
+        entries.sort_unstable_by_key(|&(name, _)| name);
+
+        let mut map = serializer.serialize_map(Some(entries.len()))?;
+
+        for (name, value) in entries {
+            map.serialize_entry(name, value)?;
+        }
+
+        map.end()
+    }
+}
+
+/// Deserializes an erased set from a map from name to value.
+///
+/// This `struct` is created by [`TypeRegistry::deserialize_seed`].
+pub struct DeserializeSet<'a, S> {
+    registry: &'a TypeRegistry<S>,
+    marker: PhantomData<fn() -> S>,
+}
+
+impl<'de, S> DeserializeSeed<'de> for DeserializeSet<'_, S>
+where
+    S: Transactional,
+{
+    type Value = S;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, S> Visitor<'de> for DeserializeSet<'_, S>
+where
+    S: Transactional,
+{
+    type Value = S;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map from type name to value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<S, A::Error> {
+        let mut set = S::staging();
+
+        while let Some(name) = map.next_key::<String>()? {
+            let Some(type_id) = self.registry.type_ids.get(name.as_str()) else {
+                if self.registry.skip_unknown_names {
+                    map.next_value::<de::IgnoredAny>()?;
+                    continue;
+                }
+
+                return Err(de::Error::custom(format_args!(
+                    "unknown type name `{name}`"
+                )));
+            };
+
+            let registration = &self.registry.registrations[type_id];
+            map.next_value_seed(DeserializeValue(registration, &mut set))?;
+        }
+
+        Ok(set)
+    }
+}
+
+struct DeserializeValue<'a, S>(&'a Registration<S>, &'a mut S);
+
+impl<'de, S> DeserializeSeed<'de> for DeserializeValue<'_, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+
+        (self.0.deserialize)(&mut deserializer, self.1).map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,353 @@
+use ::alloc::alloc::{alloc, handle_alloc_error, Layout};
+use ::alloc::boxed::Box;
+use ::core::cell::UnsafeCell;
+use ::core::fmt;
+use ::core::hash::{Hash, Hasher};
+use ::core::mem::{self, ManuallyDrop, MaybeUninit};
+use ::core::panic::RefUnwindSafe;
+use ::core::ptr;
+
+/// The inline buffer of a [`SmallBox`], two words.
+type Inline = MaybeUninit<[usize; 2]>;
+
+/// A box that stores small values inline instead of on the heap.
+///
+/// Values that fit in two words and are at most word-aligned, e.g. flags, ids and counters, are
+/// stored inline. Larger values are boxed. This is the type of the values in the storage of the
+/// erased sets.
+pub struct SmallBox<B: ?Sized + 'static> {
+    repr: Repr<B>,
+}
+
+enum Repr<B: ?Sized + 'static> {
+    Heap {
+        boxed: Box<B>,
+        // `None` if the box was created from a `Box<B>`, the concrete type is unknown.
+        vtable: Option<&'static Vtable<B>>,
+    },
+    Inline {
+        // The value may contain an `UnsafeCell` and be mutated through a shared reference.
+        data: UnsafeCell<Inline>,
+        vtable: &'static Vtable<B>,
+    },
+}
+
+// The inline value is only reachable as a `B`.
+unsafe impl<B: ?Sized + Sync> Sync for SmallBox<B> {}
+
+impl<B: ?Sized + RefUnwindSafe> RefUnwindSafe for SmallBox<B> {}
+
+/// Clones the value behind the pointer into a new box with the same vtable.
+type CloneFn<B> = unsafe fn(*const (), &'static Vtable<B>) -> SmallBox<B>;
+
+/// Formats the value behind the pointer.
+type DebugFn = unsafe fn(*const (), &mut fmt::Formatter<'_>) -> fmt::Result;
+
+/// Compares the values behind the pointers, which have the same type.
+type EqFn = unsafe fn(*const (), *const ()) -> bool;
+
+/// Feeds the value behind the pointer into the hasher.
+type HashFn = unsafe fn(*const (), &mut dyn Hasher);
+
+/// The functions of the type of a value in a [`SmallBox`].
+///
+/// A `Vtable` is built for a concrete type `T` with [`Vtable::new`], optional capabilities are
+/// added with the `with_*` methods.
+pub struct Vtable<B: ?Sized + 'static> {
+    as_dyn: fn(*mut ()) -> *mut B,
+    clone: Option<CloneFn<B>>,
+    debug: Option<DebugFn>,
+    eq: Option<EqFn>,
+    hash: Option<HashFn>,
+}
+
+impl<B: ?Sized> Vtable<B> {
+    /// Creates a `Vtable` without any capability.
+    ///
+    /// `as_dyn` turns a pointer to the value into a pointer to `B` with the same address, e.g.
+    /// `|ptr| ptr.cast::<T>()` with an unsizing coercion to `B`.
+    #[must_use]
+    pub const fn new(as_dyn: fn(*mut ()) -> *mut B) -> Self {
+        Self {
+            as_dyn,
+            clone: None,
+            debug: None,
+            eq: None,
+            hash: None,
+        }
+    }
+
+    /// Allows the values to be cloned with [`SmallBox::try_clone`].
+    #[must_use]
+    pub const fn with_clone<T: Clone>(mut self) -> Self {
+        self.clone = Some(clone::<T, B>);
+        self
+    }
+
+    /// Allows the values to be formatted with [`SmallBox::as_debug`].
+    #[must_use]
+    pub const fn with_debug<T: fmt::Debug>(mut self) -> Self {
+        self.debug = Some(debug::<T>);
+        self
+    }
+
+    /// Allows the values to be compared with [`SmallBox::try_eq`].
+    #[must_use]
+    pub const fn with_partial_eq<T: PartialEq>(mut self) -> Self {
+        self.eq = Some(eq::<T>);
+        self
+    }
+
+    /// Requires the values to implement [`Eq`], no function is captured.
+    #[must_use]
+    pub const fn with_eq<T: Eq>(self) -> Self {
+        self
+    }
+
+    /// Allows the values to be hashed with [`SmallBox::try_hash`].
+    #[must_use]
+    pub const fn with_hash<T: Hash>(mut self) -> Self {
+        self.hash = Some(hash::<T>);
+        self
+    }
+}
+
+unsafe fn clone<T: Clone, B: ?Sized>(ptr: *const (), vtable: &'static Vtable<B>) -> SmallBox<B> {
+    SmallBox::new((*ptr.cast::<T>()).clone(), vtable)
+}
+
+unsafe fn debug<T: fmt::Debug>(ptr: *const (), f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    (*ptr.cast::<T>()).fmt(f)
+}
+
+unsafe fn eq<T: PartialEq>(ptr: *const (), other: *const ()) -> bool {
+    *ptr.cast::<T>() == *other.cast::<T>()
+}
+
+unsafe fn hash<T: Hash>(ptr: *const (), mut state: &mut dyn Hasher) {
+    (*ptr.cast::<T>()).hash(&mut state);
+}
+
+/// A value formatted through its [`Vtable`].
+struct DebugValue<'a, B: ?Sized + 'static> {
+    boxed: &'a SmallBox<B>,
+    debug: DebugFn,
+}
+
+impl<B: ?Sized> fmt::Debug for DebugValue<'_, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        unsafe { (self.debug)(self.boxed.as_ptr(), f) }
+    }
+}
+
+impl<B: ?Sized> SmallBox<B> {
+    const fn fits_inline<T>() -> bool {
+        mem::size_of::<T>() <= mem::size_of::<Inline>()
+            && mem::align_of::<T>() <= mem::align_of::<Inline>()
+    }
+
+    /// Creates a `SmallBox` holding `value`.
+    ///
+    /// # Safety
+    ///
+    /// `vtable` must have been built for `T`.
+    pub unsafe fn new<T>(value: T, vtable: &'static Vtable<B>) -> Self {
+        if Self::fits_inline::<T>() {
+            let data = UnsafeCell::new(Inline::uninit());
+            data.get().cast::<T>().write(value);
+
+            Self {
+                repr: Repr::Inline { data, vtable },
+            }
+        } else {
+            let ptr = Box::into_raw(Box::new(value));
+
+            Self {
+                repr: Repr::Heap {
+                    boxed: Box::from_raw((vtable.as_dyn)(ptr.cast())),
+                    vtable: Some(vtable),
+                },
+            }
+        }
+    }
+
+    /// Returns `true` if the value is stored inline.
+    #[must_use]
+    pub fn is_inline(&self) -> bool {
+        matches!(self.repr, Repr::Inline { .. })
+    }
+
+    fn vtable(&self) -> Option<&'static Vtable<B>> {
+        match &self.repr {
+            Repr::Heap { vtable, .. } => *vtable,
+            Repr::Inline { vtable, .. } => Some(vtable),
+        }
+    }
+
+    fn as_ptr(&self) -> *const () {
+        match &self.repr {
+            Repr::Heap { boxed, .. } => (&**boxed as *const B).cast(),
+            Repr::Inline { data, .. } => data.get().cast::<()>(),
+        }
+    }
+
+    /// Returns a clone of the value.
+    ///
+    /// Returns [`None`] if the vtable of the value does not allow cloning, or if the box was
+    /// created from a [`Box`].
+    #[must_use]
+    pub fn try_clone(&self) -> Option<Self> {
+        let vtable = self.vtable()?;
+
+        Some(unsafe { (vtable.clone?)(self.as_ptr(), vtable) })
+    }
+
+    /// Returns the value as a [`Debug`](fmt::Debug) implementation.
+    ///
+    /// Returns [`None`] if the vtable of the value does not allow formatting, or if the box was
+    /// created from a [`Box`].
+    #[must_use]
+    pub fn as_debug(&self) -> Option<impl fmt::Debug + '_> {
+        let debug = self.vtable()?.debug?;
+
+        Some(DebugValue { boxed: self, debug })
+    }
+
+    /// Returns `true` if the values are equal.
+    ///
+    /// Returns [`None`] if the vtable of the value does not allow comparisons, or if the box was
+    /// created from a [`Box`].
+    ///
+    /// # Safety
+    ///
+    /// The values must have the same type.
+    #[must_use]
+    pub unsafe fn try_eq(&self, other: &Self) -> Option<bool> {
+        let eq = self.vtable()?.eq?;
+
+        Some(eq(self.as_ptr(), other.as_ptr()))
+    }
+
+    /// Feeds the value into the given [`Hasher`].
+    ///
+    /// Returns `false`, without hashing anything, if the vtable of the value does not allow
+    /// hashing, or if the box was created from a [`Box`].
+    pub fn try_hash(&self, state: &mut dyn Hasher) -> bool {
+        let Some(hash) = self.vtable().and_then(|vtable| vtable.hash) else {
+            return false;
+        };
+
+        unsafe { hash(self.as_ptr(), state) };
+
+        true
+    }
+
+    /// Moves the value to the heap.
+    ///
+    /// Values that are already on the heap are not moved.
+    #[must_use]
+    pub fn into_box(self) -> Box<B> {
+        let mut this = ManuallyDrop::new(self);
+
+        match &mut this.repr {
+            Repr::Heap { boxed, .. } => unsafe { ptr::read(boxed) },
+            Repr::Inline { data, vtable } => {
+                let src = data.get().cast::<u8>();
+                let layout = Layout::for_value(unsafe { &*(vtable.as_dyn)(src.cast()) });
+
+                let dst = if layout.size() == 0 {
+                    // A dangling, well-aligned pointer.
+                    layout.align() as *mut u8
+                } else {
+                    let dst = unsafe { alloc(layout) };
+
+                    if dst.is_null() {
+                        handle_alloc_error(layout);
+                    }
+
+                    unsafe { ptr::copy_nonoverlapping(src, dst, layout.size()) };
+
+                    dst
+                };
+
+                unsafe { Box::from_raw((vtable.as_dyn)(dst.cast())) }
+            }
+        }
+    }
+
+    /// Moves the value out of the box.
+    ///
+    /// # Safety
+    ///
+    /// The value must be a `T`.
+    #[must_use]
+    pub unsafe fn into_inner_unchecked<T>(self) -> T {
+        let mut this = ManuallyDrop::new(self);
+
+        match &mut this.repr {
+            Repr::Heap { boxed, .. } => {
+                let ptr = Box::into_raw(ptr::read(boxed)).cast::<T>();
+
+                *Box::from_raw(ptr)
+            }
+            Repr::Inline { data, .. } => data.get().cast::<T>().read(),
+        }
+    }
+}
+
+impl<B: ?Sized> Drop for SmallBox<B> {
+    fn drop(&mut self) {
+        if let Repr::Inline { data, vtable } = &mut self.repr {
+            unsafe { ptr::drop_in_place((vtable.as_dyn)(data.get().cast())) };
+        }
+    }
+}
+
+impl<B: ?Sized> From<Box<B>> for SmallBox<B> {
+    fn from(boxed: Box<B>) -> Self {
+        Self {
+            repr: Repr::Heap {
+                boxed,
+                vtable: None,
+            },
+        }
+    }
+}
+
+impl<B: ?Sized> AsRef<B> for SmallBox<B> {
+    fn as_ref(&self) -> &B {
+        match &self.repr {
+            Repr::Heap { boxed, .. } => boxed,
+            Repr::Inline { data, vtable } => unsafe { &*(vtable.as_dyn)(data.get().cast()) },
+        }
+    }
+}
+
+impl<B: ?Sized> AsMut<B> for SmallBox<B> {
+    fn as_mut(&mut self) -> &mut B {
+        match &mut self.repr {
+            Repr::Heap { boxed, .. } => boxed,
+            Repr::Inline { data, vtable } => unsafe { &mut *(vtable.as_dyn)(data.get().cast()) },
+        }
+    }
+}
+
+impl<B: ?Sized> ::core::ops::Deref for SmallBox<B> {
+    type Target = B;
+
+    fn deref(&self) -> &B {
+        self.as_ref()
+    }
+}
+
+impl<B: ?Sized> ::core::ops::DerefMut for SmallBox<B> {
+    fn deref_mut(&mut self) -> &mut B {
+        self.as_mut()
+    }
+}
+
+impl<B: ?Sized + fmt::Debug> fmt::Debug for SmallBox<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
@@ -0,0 +1,713 @@
+//! Storage backends for the erased sets.
+//!
+//! An erased set stores its values in a map from [`TypeId`] to [`SmallBox`](crate::SmallBox), the
+//! backend of that map can be selected when generating a set with
+//! [`impl_erased_set!`](crate::impl_erased_set):
+//!
+//! | backend          | lookup   | capacity | description                                        |
+//! | ---------------- | -------- | -------- | -------------------------------------------------- |
+//! | [`BTreeStorage`] | O(log n) | no       | A [`BTreeMap`], the default backend.               |
+//! | `HashStorage`    | O(1)     | yes      | A `hashbrown` table, requires the `hashbrown` feature. |
+//! | [`VecStorage`]   | O(log n) | yes      | A sorted [`Vec`], fastest for a handful of types.  |
+//!
+//! # Example
+//!
+//! ```
+//! use erased_set::storage::VecStorage;
+//!
+//! erased_set::impl_erased_set! {
+//!     /// A set of erased types backed by a sorted vector.
+//!     #[derive(Default)]
+//!     pub struct SmallErasedSet: Any in VecStorage;
+//! }
+//!
+//! let mut set = SmallErasedSet::with_capacity(2);
+//! set.insert("a");
+//! set.insert(1_u32);
+//! assert_eq!(set.get::<u32>(), Some(&1));
+//! ```
+
+use ::alloc::collections::{btree_map, BTreeMap};
+use ::alloc::vec::{self, Vec};
+use ::core::any::TypeId;
+use ::core::hash::Hasher;
+use ::core::iter::Map;
+use ::core::slice;
+
+/// A map from [`TypeId`] to value, backing an erased set.
+///
+/// # Safety
+///
+/// The erased sets cast the values returned by the storage to the type of their key without
+/// checking it, implementations must therefore behave like a map:
+///
+/// - a value is only returned for the type id it was inserted with, by the methods of the
+///   storage and by its entries,
+/// - [`get_many_mut`](Self::get_many_mut) returns references to distinct values,
+/// - the iterators yield each entry once.
+pub unsafe trait Storage: Default {
+    /// The type of the stored values.
+    type Value;
+
+    /// An iterator over the entries of the storage.
+    type Iter<'a>: Iterator<Item = (&'a TypeId, &'a Self::Value)>
+    where
+        Self: 'a;
+
+    /// A mutable iterator over the entries of the storage.
+    type IterMut<'a>: Iterator<Item = (&'a TypeId, &'a mut Self::Value)>
+    where
+        Self: 'a;
+
+    /// An owning iterator over the entries of the storage.
+    type IntoIter: Iterator<Item = (TypeId, Self::Value)>;
+
+    /// A view into an occupied entry of the storage.
+    type OccupiedEntry<'a>: OccupiedStorageEntry<'a, Value = Self::Value>
+    where
+        Self: 'a;
+
+    /// A view into a vacant entry of the storage.
+    type VacantEntry<'a>: VacantStorageEntry<'a, Value = Self::Value>
+    where
+        Self: 'a;
+
+    /// Creates an empty storage with space for at least `capacity` entries.
+    ///
+    /// Backends that do not support pre-allocation ignore the capacity.
+    #[must_use]
+    fn with_capacity(capacity: usize) -> Self {
+        let _ = capacity;
+
+        Self::default()
+    }
+
+    /// Reserves capacity for at least `additional` more entries.
+    ///
+    /// Backends that do not support pre-allocation do nothing.
+    fn reserve(&mut self, additional: usize) {
+        let _ = additional;
+    }
+
+    /// Shrinks the capacity of the storage as much as possible.
+    ///
+    /// Backends that do not support pre-allocation do nothing.
+    fn shrink_to_fit(&mut self) {}
+
+    /// Returns the number of entries in the storage.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the storage contains no entries.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the storage contains an entry for `type_id`.
+    fn contains_key(&self, type_id: &TypeId) -> bool {
+        self.get(type_id).is_some()
+    }
+
+    /// Returns a reference to the value of `type_id`.
+    fn get(&self, type_id: &TypeId) -> Option<&Self::Value>;
+
+    /// Returns a mutable reference to the value of `type_id`.
+    fn get_mut(&mut self, type_id: &TypeId) -> Option<&mut Self::Value>;
+
+    /// Returns mutable references to the values of `N` type ids at once.
+    ///
+    /// The default implementation walks the whole storage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `type_ids` contains the same type id more than once.
+    fn get_many_mut<const N: usize>(
+        &mut self,
+        type_ids: [&TypeId; N],
+    ) -> [Option<&mut Self::Value>; N] {
+        assert_distinct(&type_ids);
+
+        let mut values = [(); N].map(|()| None);
+
+        for (type_id, value) in self.iter_mut() {
+            if let Some(index) = type_ids.iter().position(|&other| other == type_id) {
+                values[index] = Some(value);
+            }
+        }
+
+        values
+    }
+
+    /// Inserts `value` for `type_id`, returning the replaced value.
+    fn insert(&mut self, type_id: TypeId, value: Self::Value) -> Option<Self::Value>;
+
+    /// Removes and returns the value of `type_id`.
+    fn remove(&mut self, type_id: &TypeId) -> Option<Self::Value>;
+
+    /// Removes all entries.
+    fn clear(&mut self);
+
+    /// Retains only the entries for which `f` returns `true`.
+    fn retain(&mut self, f: impl FnMut(TypeId, &mut Self::Value) -> bool);
+
+    /// Gets the entry of `type_id` for in-place manipulation.
+    fn entry(
+        &mut self,
+        type_id: TypeId,
+    ) -> StorageEntry<Self::OccupiedEntry<'_>, Self::VacantEntry<'_>>;
+
+    /// Gets an iterator over the entries, in arbitrary order.
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /// Gets a mutable iterator over the entries, in arbitrary order.
+    fn iter_mut(&mut self) -> Self::IterMut<'_>;
+
+    /// Creates an owning iterator over the entries, in arbitrary order.
+    fn into_iter(self) -> Self::IntoIter;
+}
+
+fn assert_distinct(type_ids: &[&TypeId]) {
+    for (index, type_id) in type_ids.iter().enumerate() {
+        assert!(
+            !type_ids[..index].contains(type_id),
+            "duplicate type id in `get_many_mut`"
+        );
+    }
+}
+
+/// A view into a single entry of a [`Storage`], which may either be vacant or occupied.
+pub enum StorageEntry<O, V> {
+    /// An occupied entry.
+    Occupied(O),
+    /// A vacant entry.
+    Vacant(V),
+}
+
+/// A view into an occupied entry of a [`Storage`].
+pub trait OccupiedStorageEntry<'a> {
+    /// The type of the stored values.
+    type Value;
+
+    /// Gets a reference to the value in the entry.
+    fn get(&self) -> &Self::Value;
+
+    /// Gets a mutable reference to the value in the entry.
+    fn get_mut(&mut self) -> &mut Self::Value;
+
+    /// Converts the entry into a mutable reference to its value.
+    fn into_mut(self) -> &'a mut Self::Value;
+
+    /// Sets the value of the entry and returns the entry's old value.
+    fn insert(&mut self, value: Self::Value) -> Self::Value {
+        ::core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Takes the value out of the entry, and returns it.
+    fn remove(self) -> Self::Value;
+}
+
+/// A view into a vacant entry of a [`Storage`].
+pub trait VacantStorageEntry<'a> {
+    /// The type of the stored values.
+    type Value;
+
+    /// Sets the value of the entry, and returns a mutable reference to it.
+    fn insert(self, value: Self::Value) -> &'a mut Self::Value;
+}
+
+/// A [`Storage`] backed by a [`BTreeMap`].
+///
+/// This is the default backend, lookups are O(log n) and it does not support pre-allocation.
+pub struct BTreeStorage<V> {
+    inner: BTreeMap<TypeId, V>,
+}
+
+impl<V> BTreeStorage<V> {
+    /// Creates an empty `BTreeStorage`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            inner: BTreeMap::new(),
+        }
+    }
+}
+
+impl<V> Default for BTreeStorage<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<V> Storage for BTreeStorage<V> {
+    type Value = V;
+    type Iter<'a>
+        = btree_map::Iter<'a, TypeId, V>
+    where
+        V: 'a;
+    type IterMut<'a>
+        = btree_map::IterMut<'a, TypeId, V>
+    where
+        V: 'a;
+    type IntoIter = btree_map::IntoIter<TypeId, V>;
+    type OccupiedEntry<'a>
+        = btree_map::OccupiedEntry<'a, TypeId, V>
+    where
+        V: 'a;
+    type VacantEntry<'a>
+        = btree_map::VacantEntry<'a, TypeId, V>
+    where
+        V: 'a;
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn contains_key(&self, type_id: &TypeId) -> bool {
+        self.inner.contains_key(type_id)
+    }
+
+    fn get(&self, type_id: &TypeId) -> Option<&V> {
+        self.inner.get(type_id)
+    }
+
+    fn get_mut(&mut self, type_id: &TypeId) -> Option<&mut V> {
+        self.inner.get_mut(type_id)
+    }
+
+    fn insert(&mut self, type_id: TypeId, value: V) -> Option<V> {
+        self.inner.insert(type_id, value)
+    }
+
+    fn remove(&mut self, type_id: &TypeId) -> Option<V> {
+        self.inner.remove(type_id)
+    }
+
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(TypeId, &mut V) -> bool) {
+        self.inner.retain(|&type_id, value| f(type_id, value));
+    }
+
+    fn entry(
+        &mut self,
+        type_id: TypeId,
+    ) -> StorageEntry<Self::OccupiedEntry<'_>, Self::VacantEntry<'_>> {
+        match self.inner.entry(type_id) {
+            btree_map::Entry::Occupied(entry) => StorageEntry::Occupied(entry),
+            btree_map::Entry::Vacant(entry) => StorageEntry::Vacant(entry),
+        }
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.inner.iter()
+    }
+
+    fn iter_mut(&mut self) -> Self::IterMut<'_> {
+        self.inner.iter_mut()
+    }
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.into_iter()
+    }
+}
+
+impl<'a, V> OccupiedStorageEntry<'a> for btree_map::OccupiedEntry<'a, TypeId, V> {
+    type Value = V;
+
+    fn get(&self) -> &V {
+        self.get()
+    }
+
+    fn get_mut(&mut self) -> &mut V {
+        self.get_mut()
+    }
+
+    fn into_mut(self) -> &'a mut V {
+        self.into_mut()
+    }
+
+    fn remove(self) -> V {
+        self.remove()
+    }
+}
+
+impl<'a, V> VacantStorageEntry<'a> for btree_map::VacantEntry<'a, TypeId, V> {
+    type Value = V;
+
+    fn insert(self, value: V) -> &'a mut V {
+        self.insert(value)
+    }
+}
+
+/// A [`Storage`] backed by a [`Vec`] sorted by [`TypeId`].
+///
+/// Lookups are a binary search, insertions and removals shift the following entries. This is
+/// the most compact backend and the fastest for sets of a handful of types.
+pub struct VecStorage<V> {
+    entries: Vec<(TypeId, V)>,
+}
+
+impl<V> VecStorage<V> {
+    /// Creates an empty `VecStorage`.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    fn search(&self, type_id: &TypeId) -> Result<usize, usize> {
+        self.entries.binary_search_by(|(key, _)| key.cmp(type_id))
+    }
+}
+
+impl<V> Default for VecStorage<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<V> Storage for VecStorage<V> {
+    type Value = V;
+    type Iter<'a>
+        = Map<slice::Iter<'a, (TypeId, V)>, fn(&'a (TypeId, V)) -> (&'a TypeId, &'a V)>
+    where
+        V: 'a;
+    type IterMut<'a>
+        = Map<slice::IterMut<'a, (TypeId, V)>, fn(&'a mut (TypeId, V)) -> (&'a TypeId, &'a mut V)>
+    where
+        V: 'a;
+    type IntoIter = vec::IntoIter<(TypeId, V)>;
+    type OccupiedEntry<'a>
+        = VecOccupiedEntry<'a, V>
+    where
+        V: 'a;
+    type VacantEntry<'a>
+        = VecVacantEntry<'a, V>
+    where
+        V: 'a;
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn get(&self, type_id: &TypeId) -> Option<&V> {
+        let index = self.search(type_id).ok()?;
+
+        Some(&self.entries[index].1)
+    }
+
+    fn get_mut(&mut self, type_id: &TypeId) -> Option<&mut V> {
+        let index = self.search(type_id).ok()?;
+
+        Some(&mut self.entries[index].1)
+    }
+
+    fn get_many_mut<const N: usize>(&mut self, type_ids: [&TypeId; N]) -> [Option<&mut V>; N] {
+        assert_distinct(&type_ids);
+
+        let indices = type_ids.map(|type_id| self.search(type_id).ok());
+        let entries = self.entries.as_mut_ptr();
+
+        // The type ids are distinct, so are the indices.
+        indices.map(|index| index.map(|index| unsafe { &mut (*entries.add(index)).1 }))
+    }
+
+    fn insert(&mut self, type_id: TypeId, value: V) -> Option<V> {
+        match self.search(&type_id) {
+            Ok(index) => Some(::core::mem::replace(&mut self.entries[index].1, value)),
+            Err(index) => {
+                self.entries.insert(index, (type_id, value));
+                None
+            }
+        }
+    }
+
+    fn remove(&mut self, type_id: &TypeId) -> Option<V> {
+        let index = self.search(type_id).ok()?;
+
+        Some(self.entries.remove(index).1)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(TypeId, &mut V) -> bool) {
+        self.entries
+            .retain_mut(|(type_id, value)| f(*type_id, value));
+    }
+
+    fn entry(
+        &mut self,
+        type_id: TypeId,
+    ) -> StorageEntry<Self::OccupiedEntry<'_>, Self::VacantEntry<'_>> {
+        match self.search(&type_id) {
+            Ok(index) => StorageEntry::Occupied(VecOccupiedEntry {
+                entries: &mut self.entries,
+                index,
+            }),
+            Err(index) => StorageEntry::Vacant(VecVacantEntry {
+                entries: &mut self.entries,
+                index,
+                type_id,
+            }),
+        }
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.entries.iter().map(|(type_id, value)| (type_id, value))
+    }
+
+    fn iter_mut(&mut self) -> Self::IterMut<'_> {
+        self.entries
+            .iter_mut()
+            .map(|(type_id, value)| (&*type_id, value))
+    }
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// A view into an occupied entry of a [`VecStorage`].
+pub struct VecOccupiedEntry<'a, V> {
+    entries: &'a mut Vec<(TypeId, V)>,
+    index: usize,
+}
+
+impl<'a, V> OccupiedStorageEntry<'a> for VecOccupiedEntry<'a, V> {
+    type Value = V;
+
+    fn get(&self) -> &V {
+        &self.entries[self.index].1
+    }
+
+    fn get_mut(&mut self) -> &mut V {
+        &mut self.entries[self.index].1
+    }
+
+    fn into_mut(self) -> &'a mut V {
+        &mut self.entries[self.index].1
+    }
+
+    fn remove(self) -> V {
+        self.entries.remove(self.index).1
+    }
+}
+
+/// A view into a vacant entry of a [`VecStorage`].
+pub struct VecVacantEntry<'a, V> {
+    entries: &'a mut Vec<(TypeId, V)>,
+    index: usize,
+    type_id: TypeId,
+}
+
+impl<'a, V> VacantStorageEntry<'a> for VecVacantEntry<'a, V> {
+    type Value = V;
+
+    fn insert(self, value: V) -> &'a mut V {
+        self.entries.insert(self.index, (self.type_id, value));
+
+        &mut self.entries[self.index].1
+    }
+}
+
+/// A [`Hasher`] for [`TypeId`]s.
+///
+/// A [`TypeId`] is already a hash of its type, so this hasher passes it through as is.
+#[derive(Default, Clone, Copy)]
+pub struct TypeIdHasher {
+    hash: u64,
+}
+
+impl Hasher for TypeIdHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        // Only reached if the `Hash` implementation of `TypeId` stops writing a single `u64`.
+        self.hash = bytes.iter().fold(self.hash, |hash, &byte| {
+            hash.rotate_left(8) ^ u64::from(byte)
+        });
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.hash = value;
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(feature = "hashbrown")]
+pub use self::hash::HashStorage;
+
+#[cfg(feature = "hashbrown")]
+mod hash {
+    use super::{OccupiedStorageEntry, Storage, StorageEntry, TypeIdHasher, VacantStorageEntry};
+    use ::core::any::TypeId;
+    use ::core::hash::BuildHasherDefault;
+    use ::hashbrown::hash_map::{self, HashMap};
+
+    type BuildTypeIdHasher = BuildHasherDefault<TypeIdHasher>;
+
+    /// A [`Storage`] backed by a [`hashbrown`] table with a [`TypeIdHasher`].
+    ///
+    /// Lookups are O(1) and pre-allocation is supported.
+    pub struct HashStorage<V> {
+        inner: HashMap<TypeId, V, BuildTypeIdHasher>,
+    }
+
+    impl<V> HashStorage<V> {
+        /// Creates an empty `HashStorage`.
+        #[must_use]
+        pub fn new() -> Self {
+            Self {
+                inner: HashMap::with_hasher(BuildTypeIdHasher::default()),
+            }
+        }
+    }
+
+    impl<V> Default for HashStorage<V> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    unsafe impl<V> Storage for HashStorage<V> {
+        type Value = V;
+        type Iter<'a>
+            = hash_map::Iter<'a, TypeId, V>
+        where
+            V: 'a;
+        type IterMut<'a>
+            = hash_map::IterMut<'a, TypeId, V>
+        where
+            V: 'a;
+        type IntoIter = hash_map::IntoIter<TypeId, V>;
+        type OccupiedEntry<'a>
+            = hash_map::OccupiedEntry<'a, TypeId, V, BuildTypeIdHasher>
+        where
+            V: 'a;
+        type VacantEntry<'a>
+            = hash_map::VacantEntry<'a, TypeId, V, BuildTypeIdHasher>
+        where
+            V: 'a;
+
+        fn with_capacity(capacity: usize) -> Self {
+            Self {
+                inner: HashMap::with_capacity_and_hasher(capacity, BuildTypeIdHasher::default()),
+            }
+        }
+
+        fn reserve(&mut self, additional: usize) {
+            self.inner.reserve(additional);
+        }
+
+        fn shrink_to_fit(&mut self) {
+            self.inner.shrink_to_fit();
+        }
+
+        fn len(&self) -> usize {
+            self.inner.len()
+        }
+
+        fn contains_key(&self, type_id: &TypeId) -> bool {
+            self.inner.contains_key(type_id)
+        }
+
+        fn get(&self, type_id: &TypeId) -> Option<&V> {
+            self.inner.get(type_id)
+        }
+
+        fn get_mut(&mut self, type_id: &TypeId) -> Option<&mut V> {
+            self.inner.get_mut(type_id)
+        }
+
+        fn get_many_mut<const N: usize>(&mut self, type_ids: [&TypeId; N]) -> [Option<&mut V>; N] {
+            super::assert_distinct(&type_ids);
+
+            self.inner.get_many_mut(type_ids)
+        }
+
+        fn insert(&mut self, type_id: TypeId, value: V) -> Option<V> {
+            self.inner.insert(type_id, value)
+        }
+
+        fn remove(&mut self, type_id: &TypeId) -> Option<V> {
+            self.inner.remove(type_id)
+        }
+
+        fn clear(&mut self) {
+            self.inner.clear();
+        }
+
+        fn retain(&mut self, mut f: impl FnMut(TypeId, &mut V) -> bool) {
+            self.inner.retain(|&type_id, value| f(type_id, value));
+        }
+
+        fn entry(
+            &mut self,
+            type_id: TypeId,
+        ) -> StorageEntry<Self::OccupiedEntry<'_>, Self::VacantEntry<'_>> {
+            match self.inner.entry(type_id) {
+                hash_map::Entry::Occupied(entry) => StorageEntry::Occupied(entry),
+                hash_map::Entry::Vacant(entry) => StorageEntry::Vacant(entry),
+            }
+        }
+
+        fn iter(&self) -> Self::Iter<'_> {
+            self.inner.iter()
+        }
+
+        fn iter_mut(&mut self) -> Self::IterMut<'_> {
+            self.inner.iter_mut()
+        }
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.inner.into_iter()
+        }
+    }
+
+    impl<'a, V> OccupiedStorageEntry<'a> for hash_map::OccupiedEntry<'a, TypeId, V, BuildTypeIdHasher> {
+        type Value = V;
+
+        fn get(&self) -> &V {
+            self.get()
+        }
+
+        fn get_mut(&mut self) -> &mut V {
+            self.get_mut()
+        }
+
+        fn into_mut(self) -> &'a mut V {
+            self.into_mut()
+        }
+
+        fn remove(self) -> V {
+            self.remove()
+        }
+    }
+
+    impl<'a, V> VacantStorageEntry<'a> for hash_map::VacantEntry<'a, TypeId, V, BuildTypeIdHasher> {
+        type Value = V;
+
+        fn insert(self, value: V) -> &'a mut V {
+            self.insert(value)
+        }
+    }
+}
@@ -0,0 +1,617 @@
+use ::alloc::collections::BTreeMap;
+use ::core::any::{Any, TypeId};
+use ::core::fmt;
+
+use crate::storage::{BTreeStorage, Storage};
+use crate::{Entry, OccupiedEntry, SmallBox, VacantEntry};
+
+/// A point in the history of an erased tracked set, see
+/// [`ErasedTrackedSet::tick`].
+///
+/// Ticks of the same set are totally ordered, a later change has a greater tick.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tick(u64);
+
+/// Advances `tick` and records it as the latest change to `type_id`.
+fn touch(tick: &mut Tick, ticks: &mut BTreeMap<TypeId, Tick>, type_id: TypeId) {
+    tick.0 += 1;
+    ticks.insert(type_id, *tick);
+}
+
+/// A view into a single type in an erased tracked set, which may either be vacant or occupied.
+///
+/// This `enum` is constructed from the `entry` method on the erased tracked sets. Unlike an
+/// [`Entry`], the type is only marked as changed once a value is inserted, removed or mutably
+/// borrowed through the entry.
+pub enum TrackedEntry<'a, T, S: Storage + 'a = BTreeStorage<SmallBox<dyn Any>>> {
+    /// An occupied entry.
+    Occupied(TrackedOccupiedEntry<'a, T, S>),
+    /// A vacant entry.
+    Vacant(TrackedVacantEntry<'a, T, S>),
+}
+
+/// A view into an occupied entry in an erased tracked set. It is part of the [`TrackedEntry`]
+/// enum.
+pub struct TrackedOccupiedEntry<'a, T, S: Storage + 'a = BTreeStorage<SmallBox<dyn Any>>> {
+    inner: OccupiedEntry<'a, T, S>,
+    tick: &'a mut Tick,
+    ticks: &'a mut BTreeMap<TypeId, Tick>,
+}
+
+/// A view into a vacant entry in an erased tracked set. It is part of the [`TrackedEntry`] enum.
+pub struct TrackedVacantEntry<'a, T, S: Storage + 'a = BTreeStorage<SmallBox<dyn Any>>> {
+    inner: VacantEntry<'a, T, S>,
+    tick: &'a mut Tick,
+    ticks: &'a mut BTreeMap<TypeId, Tick>,
+}
+
+impl<'a, T, B, S> TrackedEntry<'a, T, S>
+where
+    T: Any,
+    B: ?Sized + Any,
+    S: Storage<Value = SmallBox<B>> + 'a,
+{
+    fn new(
+        entry: Entry<'a, T, S>,
+        tick: &'a mut Tick,
+        ticks: &'a mut BTreeMap<TypeId, Tick>,
+    ) -> Self {
+        match entry {
+            Entry::Occupied(inner) => Self::Occupied(TrackedOccupiedEntry { inner, tick, ticks }),
+            Entry::Vacant(inner) => Self::Vacant(TrackedVacantEntry { inner, tick, ticks }),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting `default` if empty, and returns a mutable
+    /// reference to the value in the entry. `T` is marked as changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedTrackedSet;
+    ///
+    /// let mut set = ErasedTrackedSet::new();
+    /// let tick = set.tick();
+    /// *set.entry::<u32>().or_insert(1) += 10;
+    /// assert_eq!(set.get::<u32>(), Some(&11));
+    /// assert!(set.changed_since::<u32>(tick));
+    /// ```
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of `f` if empty, and returns a
+    /// mutable reference to the value in the entry. `T` is marked as changed.
+    pub fn or_insert_with(self, f: impl FnOnce() -> T) -> &'a mut T {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the default value if empty, and returns a
+    /// mutable reference to the value in the entry. `T` is marked as changed.
+    pub fn or_default(self) -> &'a mut T
+    where
+        T: Default,
+    {
+        self.or_insert_with(T::default)
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any potential inserts,
+    /// marking `T` as changed if the entry is occupied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::{ErasedTrackedSet, TrackedEntry};
+    ///
+    /// let mut set = ErasedTrackedSet::new();
+    /// let tick = set.tick();
+    /// assert!(matches!(set.entry::<u32>().and_modify(|x| *x += 1), TrackedEntry::Vacant(_)));
+    /// assert!(!set.changed_since::<u32>(tick));
+    /// ```
+    #[must_use]
+    pub fn and_modify(mut self, f: impl FnOnce(&mut T)) -> Self {
+        if let Self::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+
+        self
+    }
+}
+
+impl<'a, T, B, S> TrackedOccupiedEntry<'a, T, S>
+where
+    T: Any,
+    B: ?Sized + Any,
+    S: Storage<Value = SmallBox<B>> + 'a,
+{
+    /// Gets a reference to the value in the entry, without marking `T` as changed.
+    #[must_use]
+    pub fn get(&self) -> &T {
+        self.inner.get()
+    }
+
+    /// Gets a mutable reference to the value in the entry, marking `T` as changed.
+    ///
+    /// If you need a reference to the `TrackedOccupiedEntry` which may outlive the destruction
+    /// of the [`TrackedEntry`] value, see [`into_mut`](Self::into_mut).
+    #[must_use]
+    pub fn get_mut(&mut self) -> &mut T {
+        touch(self.tick, self.ticks, TypeId::of::<T>());
+        self.inner.get_mut()
+    }
+
+    /// Converts the entry into a mutable reference to its value, marking `T` as changed.
+    ///
+    /// If you need multiple references to the `TrackedOccupiedEntry`, see
+    /// [`get_mut`](Self::get_mut).
+    #[must_use]
+    pub fn into_mut(self) -> &'a mut T {
+        touch(self.tick, self.ticks, TypeId::of::<T>());
+        self.inner.into_mut()
+    }
+
+    /// Sets the value of the entry and returns the entry's old value, marking `T` as changed.
+    pub fn insert(&mut self, value: T) -> T {
+        touch(self.tick, self.ticks, TypeId::of::<T>());
+        self.inner.insert(value)
+    }
+
+    /// Takes the value out of the entry, and returns it, marking `T` as changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::{ErasedTrackedSet, TrackedEntry};
+    ///
+    /// let mut set = ErasedTrackedSet::new();
+    /// set.insert("a");
+    ///
+    /// let tick = set.tick();
+    /// if let TrackedEntry::Occupied(entry) = set.entry::<&str>() {
+    ///     assert_eq!(entry.get(), &"a");
+    ///     assert_eq!(entry.remove(), "a");
+    /// }
+    /// assert!(set.changed_since::<&str>(tick));
+    /// ```
+    pub fn remove(self) -> T {
+        touch(self.tick, self.ticks, TypeId::of::<T>());
+        self.inner.remove()
+    }
+}
+
+impl<'a, T, B, S> TrackedVacantEntry<'a, T, S>
+where
+    T: Any,
+    B: ?Sized + Any,
+    S: Storage<Value = SmallBox<B>> + 'a,
+{
+    /// Sets the value of the entry, and returns a mutable reference to it, marking `T` as
+    /// changed.
+    pub fn insert(self, value: T) -> &'a mut T {
+        touch(self.tick, self.ticks, TypeId::of::<T>());
+        self.inner.insert(value)
+    }
+}
+
+macro_rules! impl_erased_tracked_set {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident: Any $(+ $bound:ident)*;
+
+        $inner:ident
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            set: crate::$inner,
+            tick: Tick,
+            ticks: BTreeMap<TypeId, Tick>,
+        }
+
+        impl $name {
+            #[doc = concat!("Creates an empty [`", stringify!($name), "`].")]
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// ```
+            #[must_use]
+            pub fn new() -> Self {
+                Self {
+                    set: crate::$inner::new(),
+                    tick: Tick::default(),
+                    ticks: BTreeMap::new(),
+                }
+            }
+
+            /// Returns `true` if the set contains no instances of any type.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let set = ", stringify!($name), "::new();")]
+            /// assert!(set.is_empty());
+            /// ```
+            #[must_use]
+            pub fn is_empty(&self) -> bool {
+                self.set.is_empty()
+            }
+
+            /// Returns the number of types in the set.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.insert("a");
+            /// assert_eq!(set.len(), 2);
+            /// ```
+            #[must_use]
+            pub fn len(&self) -> usize {
+                self.set.len()
+            }
+
+            /// Clears the set, removing all values. Each removed type counts as changed.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            ///
+            /// let tick = set.tick();
+            /// set.clear();
+            /// assert!(set.is_empty());
+            /// assert!(set.changed_since::<u8>(tick));
+            /// ```
+            pub fn clear(&mut self) {
+                self.tick.0 += 1;
+
+                for type_id in self.set.type_ids() {
+                    self.ticks.insert(*type_id, self.tick);
+                }
+
+                self.set.clear();
+            }
+
+            /// Returns `true` if the set contains an instance of `T`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert!(set.contains::<&str>());
+            /// assert!(!set.contains::<u8>());
+            /// ```
+            #[must_use]
+            pub fn contains<T>(&self) -> bool
+            where
+                T: Any,
+            {
+                self.set.contains::<T>()
+            }
+
+            /// Returns a reference to an instance of `T`.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            /// assert_eq!(set.get::<&str>(), Some(&"a"));
+            /// assert_eq!(set.get::<u8>(), None);
+            /// ```
+            #[must_use]
+            pub fn get<T>(&self) -> Option<&T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.set.get::<T>()
+            }
+
+            /// Returns a mutable reference to an instance of `T`, marking `T` as changed.
+            ///
+            /// If the set does not have an instance of `T`, [`None`] is returned and `T` is not
+            /// marked.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            ///
+            /// let tick = set.tick();
+            /// *set.get_mut::<u8>().unwrap() += 1;
+            /// assert!(set.changed_since::<u8>(tick));
+            /// ```
+            #[must_use]
+            pub fn get_mut<T>(&mut self) -> Option<&mut T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                if self.set.contains::<T>() {
+                    self.touch(TypeId::of::<T>());
+                }
+
+                self.set.get_mut::<T>()
+            }
+
+            /// Gets the entry for type `T` in the set for in-place manipulation.
+            ///
+            /// `T` is only marked as changed once a value is inserted, removed or mutably
+            /// borrowed through the entry.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// let tick = set.tick();
+            /// let _ = set.entry::<u32>();
+            /// assert!(!set.changed_since::<u32>(tick));
+            ///
+            /// *set.entry::<u32>().or_insert(0) += 1;
+            /// assert!(set.changed_since::<u32>(tick));
+            /// ```
+            pub fn entry<T>(
+                &mut self,
+            ) -> TrackedEntry<'_, T, BTreeStorage<SmallBox<dyn Any $(+ $bound)*>>>
+            where
+                T: Any $(+ $bound)*,
+            {
+                TrackedEntry::new(self.set.entry::<T>(), &mut self.tick, &mut self.ticks)
+            }
+
+            /// Insert an instance of type `T` into the set, marking `T` as changed.
+            ///
+            /// Returns the replaced value, if any.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.insert("a"), None);
+            /// assert_eq!(set.insert("b"), Some("a"));
+            /// ```
+            pub fn insert<T>(&mut self, value: T) -> Option<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                self.touch(TypeId::of::<T>());
+                self.set.insert(value)
+            }
+
+            /// Remove and return an instance of type `T` from the set, marking `T` as changed.
+            ///
+            /// If the set did not have this type present, [`None`] is returned and `T` is not
+            /// marked.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert("a");
+            ///
+            /// let tick = set.tick();
+            /// assert_eq!(set.remove::<&str>(), Some("a"));
+            /// assert!(set.changed_since::<&str>(tick));
+            /// ```
+            pub fn remove<T>(&mut self) -> Option<T>
+            where
+                T: Any $(+ $bound)*,
+            {
+                let value = self.set.remove::<T>()?;
+                self.touch(TypeId::of::<T>());
+                Some(value)
+            }
+
+            /// Gets an iterator over the [`TypeId`]s of stored elements, in arbitrary order.
+            pub fn type_ids(&self) -> impl Iterator<Item = &TypeId> {
+                self.set.type_ids()
+            }
+
+            /// Returns the tick of the latest change to the set.
+            ///
+            /// Keep it to later find out what changed since, with
+            /// [`changed_since`](Self::changed_since) or
+            /// [`changed_types_since`](Self::changed_types_since).
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// let before = set.tick();
+            /// set.insert(1_u8);
+            /// assert!(set.tick() > before);
+            /// ```
+            #[must_use]
+            pub fn tick(&self) -> Tick {
+                self.tick
+            }
+
+            /// Returns the tick of the latest change to `T`, including its removal.
+            ///
+            /// If `T` never changed, [`None`] is returned.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// assert_eq!(set.last_changed::<u8>(), None);
+            /// set.insert(1_u8);
+            /// assert_eq!(set.last_changed::<u8>(), Some(set.tick()));
+            /// ```
+            #[must_use]
+            pub fn last_changed<T>(&self) -> Option<Tick>
+            where
+                T: Any,
+            {
+                self.ticks.get(&TypeId::of::<T>()).copied()
+            }
+
+            /// Returns `true` if `T` changed after `tick`.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            ///
+            /// let tick = set.tick();
+            /// assert!(!set.changed_since::<u8>(tick));
+            /// set.insert(2_u8);
+            /// assert!(set.changed_since::<u8>(tick));
+            /// ```
+            #[must_use]
+            pub fn changed_since<T>(&self, tick: Tick) -> bool
+            where
+                T: Any,
+            {
+                self.last_changed::<T>().map_or(false, |changed| changed > tick)
+            }
+
+            /// Gets an iterator over the [`TypeId`]s of the types that changed after `tick`,
+            /// including removed types, in arbitrary order.
+            ///
+            /// # Examples
+            ///
+            /// ```
+            /// use std::any::TypeId;
+            #[doc = concat!("use erased_set::", stringify!($name), ";")]
+            ///
+            #[doc = concat!("let mut set = ", stringify!($name), "::new();")]
+            /// set.insert(1_u8);
+            /// set.insert("a");
+            ///
+            /// let tick = set.tick();
+            /// set.insert(2_u8);
+            ///
+            /// let changed: Vec<_> = set.changed_types_since(tick).collect();
+            /// assert_eq!(changed, [&TypeId::of::<u8>()]);
+            /// ```
+            pub fn changed_types_since(&self, tick: Tick) -> impl Iterator<Item = &TypeId> {
+                self.ticks
+                    .iter()
+                    .filter(move |(_, changed)| **changed > tick)
+                    .map(|(type_id, _)| type_id)
+            }
+
+            #[doc = concat!("Returns the underlying [`", stringify!($inner), "`].")]
+            ///
+            /// It gives read access to the methods that this type does not forward, mutable
+            /// access is not given since it would bypass the change tracking.
+            #[must_use]
+            pub fn as_set(&self) -> &crate::$inner {
+                &self.set
+            }
+
+            #[doc = concat!("Returns the underlying [`", stringify!($inner), "`], dropping the change ticks.")]
+            #[must_use]
+            pub fn into_set(self) -> crate::$inner {
+                self.set
+            }
+
+            fn touch(&mut self, type_id: TypeId) {
+                touch(&mut self.tick, &mut self.ticks, type_id);
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($name))
+                    .field("set", &self.set)
+                    .field("tick", &self.tick)
+                    .finish()
+            }
+        }
+    };
+}
+
+impl_erased_tracked_set! {
+    /// An [`ErasedSet`](crate::ErasedSet) that records when each type last changed, e.g. to only
+    /// recompute the state derived from the types that changed.
+    ///
+    /// Each insertion, removal and mutable access, through [`get_mut`](Self::get_mut) or an
+    /// [`entry`](Self::entry), advances the [`Tick`] of the set and records it for the type.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use erased_set::ErasedTrackedSet;
+    ///
+    /// # #[derive(Debug, PartialEq)]
+    /// struct Camera(f32);
+    /// # #[derive(Debug, PartialEq)]
+    /// struct Viewport(u32, u32);
+    ///
+    /// let mut set = ErasedTrackedSet::new();
+    /// set.insert(Camera(1.0));
+    /// set.insert(Viewport(800, 600));
+    ///
+    /// let frame = set.tick();
+    /// set.get_mut::<Camera>().unwrap().0 = 2.0;
+    ///
+    /// assert!(set.changed_since::<Camera>(frame));
+    /// assert!(!set.changed_since::<Viewport>(frame));
+    /// ```
+    pub struct ErasedTrackedSet: Any;
+
+    ErasedSet
+}
+
+#[cfg(feature = "send")]
+impl_erased_tracked_set! {
+    /// Like [`ErasedTrackedSet`] but with a [`Send`] bound.
+    pub struct ErasedSendTrackedSet: Any + Send;
+
+    ErasedSendSet
+}
+
+#[cfg(feature = "sync")]
+impl_erased_tracked_set! {
+    /// Like [`ErasedTrackedSet`] but with a [`Send`] + [`Sync`] bound.
+    pub struct ErasedSyncTrackedSet: Any + Send + Sync;
+
+    ErasedSyncSet
+}
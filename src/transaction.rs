@@ -0,0 +1,158 @@
+use ::alloc::collections::BTreeSet;
+use ::core::any::{Any, TypeId};
+use ::core::fmt;
+
+/// The operations of an erased set used by its transactions.
+///
+/// This trait is implemented by the [`impl_erased_set!`](crate::impl_erased_set) macro.
+#[doc(hidden)]
+pub trait Transactional: Sized {
+    /// Creates an empty set, staging the insertions of a transaction.
+    fn staging() -> Self;
+
+    /// Returns a reference to the value of type `type_id`.
+    fn get_any(&self, type_id: &TypeId) -> Option<&dyn Any>;
+
+    /// Returns the name of the type `type_id`, only known in debug builds.
+    fn type_name(&self, type_id: &TypeId) -> Option<&'static str>;
+
+    /// Drops the value of type `type_id`.
+    fn discard(&mut self, type_id: &TypeId);
+
+    /// Removes the types of `removed`, then moves the values of `staged` into the set.
+    ///
+    /// Returns the values that were removed or replaced.
+    fn commit(&mut self, staged: Self, removed: BTreeSet<TypeId>) -> Self;
+}
+
+/// The insertion of a value of type `T`, with the bounds of the set.
+///
+/// This trait is implemented by the [`impl_erased_set!`](crate::impl_erased_set) macro.
+#[doc(hidden)]
+pub trait Stage<T>: Transactional {
+    /// Inserts `value` into the set.
+    fn stage(&mut self, value: T);
+}
+
+/// Mutations staged on an erased set, applied by the `transaction` method on the erased sets
+/// once its closure returns `Ok`.
+///
+/// Reads see the staged mutations on top of the values of the set.
+pub struct Transaction<'a, S> {
+    set: &'a S,
+    staged: S,
+    removed: BTreeSet<TypeId>,
+}
+
+impl<'a, S> Transaction<'a, S>
+where
+    S: Transactional,
+{
+    #[doc(hidden)]
+    pub fn new(set: &'a S) -> Self {
+        Self {
+            set,
+            staged: S::staging(),
+            removed: BTreeSet::new(),
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn into_parts(self) -> (S, BTreeSet<TypeId>) {
+        (self.staged, self.removed)
+    }
+
+    /// Stages the insertion of an instance of type `T`, replacing any instance of the set or
+    /// staged before.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedSet;
+    ///
+    /// let mut set = ErasedSet::new();
+    /// set.insert("a");
+    ///
+    /// let replaced = set
+    ///     .transaction(|tx| {
+    ///         tx.insert("b");
+    ///         assert_eq!(tx.get::<&str>(), Some(&"b"));
+    ///         Ok::<_, ()>(())
+    ///     })
+    ///     .unwrap();
+    ///
+    /// assert_eq!(set.get::<&str>(), Some(&"b"));
+    /// assert_eq!(replaced.get::<&str>(), Some(&"a"));
+    /// ```
+    pub fn insert<T>(&mut self, value: T)
+    where
+        S: Stage<T>,
+    {
+        self.staged.stage(value);
+    }
+
+    /// Stages the removal of the instance of type `T`, discarding any instance staged before.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use erased_set::ErasedSet;
+    ///
+    /// let mut set = ErasedSet::new();
+    /// set.insert("a");
+    ///
+    /// set.transaction(|tx| {
+    ///     tx.remove::<&str>();
+    ///     assert!(!tx.contains::<&str>());
+    ///     Ok::<_, ()>(())
+    /// })
+    /// .unwrap();
+    ///
+    /// assert!(!set.contains::<&str>());
+    /// ```
+    pub fn remove<T>(&mut self)
+    where
+        T: Any,
+    {
+        let type_id = TypeId::of::<T>();
+
+        self.staged.discard(&type_id);
+        self.removed.insert(type_id);
+    }
+
+    /// Returns `true` if the set contains an instance of `T` once the transaction is
+    /// committed.
+    #[must_use]
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Any,
+    {
+        self.get::<T>().is_some()
+    }
+
+    /// Returns a reference to the instance of `T` of the set once the transaction is
+    /// committed.
+    ///
+    /// If there is no such instance, [`None`] is returned.
+    #[must_use]
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: Any,
+    {
+        let type_id = TypeId::of::<T>();
+
+        match self.staged.get_any(&type_id) {
+            Some(value) => value.downcast_ref(),
+            None if self.removed.contains(&type_id) => None,
+            None => self.set.get_any(&type_id)?.downcast_ref(),
+        }
+    }
+}
+
+impl<S> fmt::Debug for Transaction<'_, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("removed", &self.removed)
+            .finish_non_exhaustive()
+    }
+}
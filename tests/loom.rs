@@ -0,0 +1,128 @@
+//! Model checks of the locking of `ErasedConcurrentSet`, run with:
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg loom" cargo test --release --features concurrent --test loom
+//! ```
+
+#![cfg(all(loom, feature = "concurrent"))]
+
+use erased_set::ErasedConcurrentSet;
+use loom::sync::Arc;
+use loom::thread;
+
+#[derive(Debug, PartialEq)]
+struct A(u32);
+
+#[derive(Debug, PartialEq)]
+struct B(u32);
+
+#[test]
+fn insert_same_type() {
+    loom::model(|| {
+        let set = Arc::new(ErasedConcurrentSet::new());
+
+        let handle = {
+            let set = Arc::clone(&set);
+            thread::spawn(move || set.insert(A(1)))
+        };
+
+        let main = set.insert(A(2));
+        let other = handle.join().unwrap();
+
+        // One insertion replaced the other.
+        match (main, other) {
+            (None, Some(A(2))) => assert_eq!(*set.read::<A>().unwrap(), A(1)),
+            (Some(A(1)), None) => assert_eq!(*set.read::<A>().unwrap(), A(2)),
+            unexpected => panic!("unexpected insertion results: {unexpected:?}"),
+        }
+
+        assert_eq!(set.len(), 1);
+    });
+}
+
+#[test]
+fn insert_distinct_types() {
+    loom::model(|| {
+        let set = Arc::new(ErasedConcurrentSet::new());
+
+        let handle = {
+            let set = Arc::clone(&set);
+            thread::spawn(move || set.insert(A(1)))
+        };
+
+        assert_eq!(set.insert(B(1)), None);
+        assert_eq!(handle.join().unwrap(), None);
+
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.remove::<A>(), Some(A(1)));
+        assert_eq!(set.remove::<B>(), Some(B(1)));
+        assert!(set.is_empty());
+    });
+}
+
+#[test]
+fn write_is_exclusive() {
+    loom::model(|| {
+        let set = Arc::new(ErasedConcurrentSet::new());
+        set.insert(A(0));
+
+        let handle = {
+            let set = Arc::clone(&set);
+            thread::spawn(move || set.write::<A>().unwrap().0 += 1)
+        };
+
+        set.write::<A>().unwrap().0 += 1;
+        handle.join().unwrap();
+
+        assert_eq!(*set.read::<A>().unwrap(), A(2));
+    });
+}
+
+#[test]
+fn guards_of_distinct_types_do_not_block() {
+    loom::model(|| {
+        let set = Arc::new(ErasedConcurrentSet::new());
+        set.insert(A(0));
+
+        let mut guard = set.write::<A>().unwrap();
+
+        let handle = {
+            let set = Arc::clone(&set);
+            thread::spawn(move || {
+                // Would deadlock if `B` shared the lock of `A`.
+                set.insert(B(0));
+                set.write::<B>().unwrap().0 += 1;
+                set.remove::<B>()
+            })
+        };
+
+        assert_eq!(handle.join().unwrap(), Some(B(1)));
+
+        guard.0 += 1;
+        drop(guard);
+
+        assert_eq!(*set.read::<A>().unwrap(), A(1));
+    });
+}
+
+#[test]
+fn remove_while_reading() {
+    loom::model(|| {
+        let set = Arc::new(ErasedConcurrentSet::new());
+        set.insert(A(1));
+
+        let handle = {
+            let set = Arc::clone(&set);
+            thread::spawn(move || set.remove::<A>())
+        };
+
+        // Either the value is read before the removal or it is gone.
+        if let Some(guard) = set.read::<A>() {
+            assert_eq!(*guard, A(1));
+        }
+
+        assert_eq!(handle.join().unwrap(), Some(A(1)));
+        assert!(set.read::<A>().is_none());
+        assert!(set.is_empty());
+    });
+}